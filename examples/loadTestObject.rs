@@ -2,48 +2,36 @@ extern crate opengex_parser;
 
 use opengex_parser::OpengexPaser;
 
+fn print_summary(model: &OpengexPaser) {
+  let vertices = model.get_vertex();
+  let normals = model.get_normal();
+  let indices = model.get_index();
+  let texcoords = model.get_texcoords();
+  
+  for i in 0..vertices.len() {
+    println!("  Model {}: {} vertices, {} normals, {} indices", i, vertices[i].len(), normals[i].len(), indices[i].len());
+  }
+  println!("  {} models with texcoords", texcoords.len());
+  
+  for (node, animation) in model.get_animations() {
+    let (begin, end) = animation.range();
+    println!("  Animation on {}: {} to {}", node.name, begin, end);
+    for track in &animation.tracks {
+      println!("    Track targeting {} with {} keys", track.target_ref, track.key_count());
+    }
+  }
+}
+
 fn main() {
   println!("Loading Static Object");
-  let static_model = OpengexPaser::new(String::from("./examples/data/testobject/ObjectStatic.ogex"));
-  
-  let vertices = static_model.get_vertex();
-  let normals = static_model.get_normal();
-  let indices = static_model.get_index();
-  let texcoords = static_model.get_texcoords();
+  let static_model = OpengexPaser::load("./examples/data/testobject/ObjectStatic.ogex").unwrap();
+  print_summary(&static_model);
   
   println!("Loading Rotation Object");
-  let rotation_model = OpengexPaser::new(String::from("./examples/data/testobject/ObjectRotationAnimation.ogex"));
-  
-  let vertices = rotation_model.get_vertex();
-  let normals = rotation_model.get_normal();
-  let indices = rotation_model.get_index();
-  let texcoords = rotation_model.get_texcoords();
+  let rotation_model = OpengexPaser::load("./examples/data/testobject/ObjectRotationAnimation.ogex").unwrap();
+  print_summary(&rotation_model);
   
   println!("Loading Translation Object");
-  let translation_model = OpengexPaser::new(String::from("./examples/data/testobject/ObjectTranslationAnimation.ogex"));
-  
-  let vertices = translation_model.get_vertex();
-  let normals = translation_model.get_normal();
-  let indices = translation_model.get_index();
-  let texcoords = translation_model.get_texcoords();
-  
-  /*println!("\nVerticies:");
-  for vertex in vertices { 
-    print!("{:?}", vertex);
-  }
-  
-  println!("\nNormals:");
-  for normal in normals { 
-    print!("{:?}", normal);
-  }
-
-  println!("\nIndices:");
-  for index in indices { 
-    print!("{:?}", index);
-  }
-  
-  println!("\nUVs:");
-  for uv in uvs { 
-    print!("{:?}", uv);
-  }*/
+  let translation_model = OpengexPaser::load("./examples/data/testobject/ObjectTranslationAnimation.ogex").unwrap();
+  print_summary(&translation_model);
 }
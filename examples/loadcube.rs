@@ -3,7 +3,7 @@ extern crate opengex_parser;
 use opengex_parser::OpengexPaser;
 
 fn main() {
-  let model = OpengexPaser::new(String::from("./examples/data/cube.ogex"));
+  let model = OpengexPaser::load("./examples/data/cube.ogex").unwrap();
   
   let vertices = model.get_vertex();
   let normals = model.get_normal();
@@ -3,32 +3,20 @@ extern crate opengex_parser;
 use opengex_parser::OpengexPaser;
 
 fn main() {
-  let model = OpengexPaser::new(String::from("./examples/data/Holostand/Holostand.ogex"));
+  let model = OpengexPaser::load("./examples/data/Holostand/Holostand.ogex").unwrap();
   
   let vertices = model.get_vertex();
   let normals = model.get_normal();
   let indices = model.get_index();
   let texcoords = model.get_texcoords();
   
+  println!("Models: {}", vertices.len());
+  println!("Vertices: {}", vertices.iter().map(|v| v.len()).sum::<usize>());
+  println!("Normals: {}", normals.iter().map(|n| n.len()).sum::<usize>());
+  println!("Indices: {}", indices.iter().map(|i| i.len()).sum::<usize>());
+  println!("Texcoords: {}", texcoords.iter().map(|t| t.len()).sum::<usize>());
   
-  
-  /*println!("\nVerticies:");
-  for vertex in vertices { 
-    print!("{:?}", vertex);
-  }
-  
-  println!("\nNormals:");
-  for normal in normals { 
-    print!("{:?}", normal);
+  for (texture, colour) in model.get_diffuse_textures() {
+    println!("Diffuse texture: {:?} colour: {:?}", texture, colour);
   }
-
-  println!("\nIndices:");
-  for index in indices { 
-    print!("{:?}", index);
-  }
-  
-  println!("\nUVs:");
-  for uv in uvs { 
-    print!("{:?}", uv);
-  }*/
 }
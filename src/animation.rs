@@ -0,0 +1,519 @@
+use cgmath::Matrix4;
+use cgmath::Vector3;
+use cgmath::Quaternion;
+use cgmath::InnerSpace;
+use cgmath::SquareMatrix;
+
+use scalar::Scalar;
+use scene::{Node, MorphWeight};
+use transform::{TransformValue, Translation, Rotation, Scale};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+  Linear,
+  Bezier,
+  Tcb,
+  Unknown,
+}
+
+// The kind of data a key holds. Value keys are Single to Sixteen depending
+// on the size of the data, control points of bezier curves are
+// PlusControl and MinusControl, and tcb curves have one Tension,
+// Continuity and Bias scalar per key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyType {
+  Single,
+  Double,
+  Triple,
+  Quad,
+  Sixteen,
+  PlusControl,
+  MinusControl,
+  Tension,
+  Continuity,
+  Bias,
+}
+
+// What a track animates, found from the structure its target names rather
+// than from the name itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetType {
+  Xpos,
+  Ypos,
+  Zpos,
+  Xyzpos,
+  Xrot,
+  Yrot,
+  Zrot,
+  AxisRot,
+  QuaternionRot,
+  Xscale,
+  Yscale,
+  Zscale,
+  Xyzscale,
+  Transform,
+  // The index of the MorphWeight structure's morph target
+  MorphWeight(u32),
+  Unknown,
+}
+
+impl TargetType {
+  pub fn from_transform(value: &TransformValue) -> TargetType {
+    match *value {
+      TransformValue::Transform(_) => TargetType::Transform,
+      TransformValue::Translation(Translation::X(_)) => TargetType::Xpos,
+      TransformValue::Translation(Translation::Y(_)) => TargetType::Ypos,
+      TransformValue::Translation(Translation::Z(_)) => TargetType::Zpos,
+      TransformValue::Translation(Translation::Xyz(_)) => TargetType::Xyzpos,
+      TransformValue::Rotation(Rotation::X(_)) => TargetType::Xrot,
+      TransformValue::Rotation(Rotation::Y(_)) => TargetType::Yrot,
+      TransformValue::Rotation(Rotation::Z(_)) => TargetType::Zrot,
+      TransformValue::Rotation(Rotation::Axis(..)) => TargetType::AxisRot,
+      TransformValue::Rotation(Rotation::Quaternion(_)) => TargetType::QuaternionRot,
+      TransformValue::Scale(Scale::X(_)) => TargetType::Xscale,
+      TransformValue::Scale(Scale::Y(_)) => TargetType::Yscale,
+      TransformValue::Scale(Scale::Z(_)) => TargetType::Zscale,
+      TransformValue::Scale(Scale::Xyz(_)) => TargetType::Xyzscale,
+    }
+  }
+
+  // The number of floats in each value key of a track with this target
+  pub fn components(&self) -> Option<usize> {
+    match *self {
+      TargetType::Xyzpos | TargetType::Xyzscale => Some(3),
+      TargetType::AxisRot | TargetType::QuaternionRot => Some(4),
+      TargetType::Transform => Some(16),
+      TargetType::Unknown => None,
+      _ => Some(1),
+    }
+  }
+}
+
+// A sampled track value, shaped by the kind of its value keys
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrackValue {
+  Float(Scalar),
+  Vector(Vector3<Scalar>),
+  // An angle and the axis to rotate about
+  Axis(Scalar, Vector3<Scalar>),
+  Quaternion(Quaternion<Scalar>),
+  Matrix(Matrix4<Scalar>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+  pub floats: Vec<Scalar>,
+  pub key_type: KeyType,
+}
+
+impl Key {
+  pub fn new() -> Key {
+    Key {
+      floats: Vec::new(),
+      key_type: KeyType::Single,
+    }
+  }
+
+  pub fn is_value(&self) -> bool {
+    matches!(self.key_type, KeyType::Single | KeyType::Double | KeyType::Triple | KeyType::Quad | KeyType::Sixteen)
+  }
+}
+
+impl Default for Key {
+  fn default() -> Key {
+    Key::new()
+  }
+}
+
+fn value_key(keys: &[Key]) -> Option<&Key> {
+  keys.iter().find(|key| key.is_value())
+}
+
+fn control_key(keys: &[Key], key_type: KeyType) -> Option<&Key> {
+  keys.iter().find(|key| key.key_type == key_type)
+}
+
+fn bezier(p0: Scalar, p1: Scalar, p2: Scalar, p3: Scalar, s: Scalar) -> Scalar {
+  let u = 1.0 - s;
+  u*u*u*p0 + 3.0*u*u*s*p1 + 3.0*u*s*s*p2 + s*s*s*p3
+}
+
+// Finds the parameter at which a monotonic bezier time curve reaches time
+fn solve_bezier(p0: Scalar, p1: Scalar, p2: Scalar, p3: Scalar, time: Scalar) -> Scalar {
+  let (mut low, mut high) = (0.0, 1.0);
+  for _ in 0..32 {
+    let middle = 0.5*(low + high);
+    if bezier(p0, p1, p2, p3, middle) < time {
+      low = middle;
+    } else {
+      high = middle;
+    }
+  }
+  0.5*(low + high)
+}
+
+// Kochanek-Bartels tangents leaving key i and arriving at key i + 1. Each
+// is scaled by the length of its segment against its neighbour's, so keys
+// don't have to be evenly spaced.
+fn tcb_tangents(points: &[Scalar], times: &[Scalar], tension: &[Scalar], continuity: &[Scalar], bias: &[Scalar], i: usize) -> (Scalar, Scalar) {
+  let last = points.len() - 1;
+  let previous = points[i.saturating_sub(1)];
+  let (p0, p1) = (points[i], points[i + 1]);
+  let next = points[(i + 2).min(last)];
+
+  // The ends have no neighbour, so they count as evenly spaced
+  let segment = times[i + 1] - times[i];
+  let before = if i > 0 { times[i] - times[i - 1] } else { segment };
+  let after = if i + 2 <= last { times[i + 2] - times[i + 1] } else { segment };
+
+  let (t, c, b) = (tension[i], continuity[i], bias[i]);
+  let outgoing = 0.5*(1.0 - t)*((1.0 + c)*(1.0 + b)*(p0 - previous) + (1.0 - c)*(1.0 - b)*(p1 - p0));
+
+  let (t, c, b) = (tension[i + 1], continuity[i + 1], bias[i + 1]);
+  let incoming = 0.5*(1.0 - t)*((1.0 - c)*(1.0 + b)*(p1 - p0) + (1.0 + c)*(1.0 - b)*(next - p1));
+
+  (outgoing*spacing(segment, before), incoming*spacing(segment, after))
+}
+
+// What a tangent is scaled by on a segment of the given length next to one
+// of another length
+fn spacing(segment: Scalar, neighbour: Scalar) -> Scalar {
+  if segment + neighbour > 0.0 {
+    2.0*segment/(segment + neighbour)
+  } else {
+    1.0
+  }
+}
+
+fn hermite(p0: Scalar, m0: Scalar, m1: Scalar, p1: Scalar, s: Scalar) -> Scalar {
+  let s2 = s*s;
+  let s3 = s2*s;
+  (2.0*s3 - 3.0*s2 + 1.0)*p0 + (s3 - 2.0*s2 + s)*m0 + (s3 - s2)*m1 + (-2.0*s3 + 3.0*s2)*p1
+}
+
+fn key_floats(key: Option<&Key>, count: usize) -> Vec<Scalar> {
+  match key {
+    Some(key) if key.floats.len() >= count => key.floats.clone(),
+    _ => vec![0.0; count],
+  }
+}
+
+// Missing Bezier control points sit on their keys, which leaves the curve
+// a straight line between them
+fn control_floats(key: Option<&Key>, points: &[Scalar]) -> Vec<Scalar> {
+  match key {
+    Some(key) if key.floats.len() >= points.len() => key.floats.clone(),
+    _ => points.to_vec(),
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Time {
+  pub curve: Curve,
+  pub keys: Vec<Key>, // 1- 3 max
+}
+
+impl Time {
+  pub fn new() -> Time {
+    Time {
+      curve: Curve::Linear,
+      keys: Vec::with_capacity(3),
+    }
+  }
+
+  // The key times themselves
+  pub fn values(&self) -> Option<&Key> {
+    value_key(&self.keys)
+  }
+
+  pub fn minus_control(&self) -> Option<&Key> {
+    control_key(&self.keys, KeyType::MinusControl)
+  }
+
+  pub fn plus_control(&self) -> Option<&Key> {
+    control_key(&self.keys, KeyType::PlusControl)
+  }
+
+  // The index of the key at or before time, and how far along the curve
+  // to the next key time is, from 0 to 1
+  pub fn segment(&self, time: Scalar) -> Option<(usize, Scalar)> {
+    let times = &self.values()?.floats;
+    if times.is_empty() {
+      return None;
+    }
+    if times.len() == 1 || time <= times[0] {
+      return Some((0, 0.0));
+    }
+    let last = times.len() - 1;
+    if time >= times[last] {
+      return Some((last - 1, 1.0));
+    }
+
+    let i = times.iter().rposition(|&key_time| key_time <= time).unwrap_or(0).min(last - 1);
+    let (t0, t1) = (times[i], times[i + 1]);
+    if t1 <= t0 {
+      return Some((i, 1.0));
+    }
+
+    let s = match self.curve {
+      Curve::Bezier => {
+        let plus = control_floats(self.plus_control(), times);
+        let minus = control_floats(self.minus_control(), times);
+        solve_bezier(t0, plus[i], minus[i + 1], t1, time)
+      },
+      _ => (time - t0)/(t1 - t0),
+    };
+    Some((i, s))
+  }
+}
+
+impl Default for Time {
+  fn default() -> Time {
+    Time::new()
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Value {
+  pub curve: Curve,
+  pub keys: Vec<Key>, // 1- 4 max
+}
+
+impl Value {
+  pub fn new() -> Value {
+    Value {
+      curve: Curve::Linear,
+      keys: Vec::with_capacity(4),
+    }
+  }
+
+  // The values of the target at each key time
+  pub fn values(&self) -> Option<&Key> {
+    value_key(&self.keys)
+  }
+
+  pub fn minus_control(&self) -> Option<&Key> {
+    control_key(&self.keys, KeyType::MinusControl)
+  }
+
+  pub fn plus_control(&self) -> Option<&Key> {
+    control_key(&self.keys, KeyType::PlusControl)
+  }
+
+  // Evaluates the curve between key i and key i + 1 of the keys at times,
+  // returning every component of the value
+  pub fn evaluate(&self, times: &[Scalar], i: usize, s: Scalar) -> Vec<Scalar> {
+    let key_count = times.len();
+    let values = match self.values() {
+      Some(values) => &values.floats,
+      None => return Vec::new(),
+    };
+    if key_count == 0 || values.len() < key_count {
+      return Vec::new();
+    }
+    let components = values.len()/key_count;
+    if key_count == 1 || i + 1 >= key_count {
+      let i = i.min(key_count - 1);
+      return values[i*components..(i + 1)*components].to_vec();
+    }
+
+    let plus = control_floats(self.plus_control(), values);
+    let minus = control_floats(self.minus_control(), values);
+    let tension = key_floats(control_key(&self.keys, KeyType::Tension), key_count);
+    let continuity = key_floats(control_key(&self.keys, KeyType::Continuity), key_count);
+    let bias = key_floats(control_key(&self.keys, KeyType::Bias), key_count);
+
+    let mut result = Vec::with_capacity(components);
+    for c in 0..components {
+      let p0 = values[i*components + c];
+      let p1 = values[(i + 1)*components + c];
+      let value = match self.curve {
+        Curve::Bezier => bezier(p0, plus[i*components + c], minus[(i + 1)*components + c], p1, s),
+        Curve::Tcb => {
+          let points: Vec<Scalar> = (0..key_count).map(|k| values[k*components + c]).collect();
+          let (outgoing, incoming) = tcb_tangents(&points, times, &tension, &continuity, &bias, i);
+          hermite(p0, outgoing, incoming, p1, s)
+        },
+        _ => p0 + (p1 - p0)*s,
+      };
+      result.push(value);
+    }
+    result
+  }
+}
+
+impl Default for Value {
+  fn default() -> Value {
+    Value::new()
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track {
+  // The local name of the animated structure, like %xpos
+  pub target_ref: String,
+  pub target: TargetType,
+  pub time: Time,
+  pub value: Value
+}
+
+impl Track {
+  pub fn new() -> Track {
+    Track {
+      target_ref: "".to_string(),
+      target: TargetType::Unknown,
+      time: Time::new(),
+      value: Value::new(),
+    }
+  }
+
+  pub fn key_count(&self) -> usize {
+    self.time.values().map(|key| key.floats.len()).unwrap_or(0)
+  }
+
+  // The value of the track at a time given in the file's time units
+  pub fn sample(&self, time: Scalar) -> Vec<Scalar> {
+    match self.time.segment(time) {
+      Some((i, s)) => {
+        let times = self.time.values().map_or(&[][..], |key| &key.floats);
+        self.value.evaluate(times, i, s)
+      },
+      None => Vec::new(),
+    }
+  }
+
+  // The same as sample but mapped to the kind of value the keys hold. Quad
+  // keys are quaternions unless the target is an axis rotation.
+  pub fn sample_value(&self, time: Scalar) -> Option<TrackValue> {
+    let key_type = self.value.values()?.key_type;
+    let v = self.sample(time);
+    match key_type {
+      KeyType::Single if !v.is_empty() => Some(TrackValue::Float(v[0])),
+      KeyType::Triple if v.len() >= 3 => Some(TrackValue::Vector(Vector3::new(v[0], v[1], v[2]))),
+      KeyType::Quad if v.len() >= 4 => {
+        if self.target == TargetType::AxisRot {
+          Some(TrackValue::Axis(v[0], Vector3::new(v[1], v[2], v[3])))
+        } else {
+          let quaternion = Quaternion::new(v[3], v[0], v[1], v[2]);
+          if quaternion.magnitude2() == 0.0 {
+            Some(TrackValue::Quaternion(quaternion))
+          } else {
+            Some(TrackValue::Quaternion(quaternion.normalize()))
+          }
+        }
+      },
+      KeyType::Sixteen if v.len() >= 16 => {
+        Some(TrackValue::Matrix(Matrix4::new(v[0], v[1], v[2], v[3], v[4], v[5], v[6], v[7],
+                                             v[8], v[9], v[10], v[11], v[12], v[13], v[14], v[15])))
+      },
+      _ => None,
+    }
+  }
+}
+
+impl Default for Track {
+  fn default() -> Track {
+    Track::new()
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+  pub clip: u32,
+  // When begin or end aren't given the animation starts at its first key
+  // or finishes at its last key
+  pub begin: Option<Scalar>,
+  pub end: Option<Scalar>,
+  pub tracks: Vec<Track>,
+  // Seconds per unit of the key times, from the Metric unless the times
+  // were converted
+  pub time_scale: Scalar,
+}
+
+impl Animation {
+  pub fn new() -> Animation {
+    Animation {
+      clip: 0,
+      begin: None,
+      end: None,
+      tracks: Vec::new(),
+      time_scale: 1.0,
+    }
+  }
+
+  pub fn first_key_time(&self) -> Option<Scalar> {
+    self.tracks.iter()
+      .filter_map(|track| track.time.values())
+      .filter_map(|key| key.floats.first().cloned())
+      .fold(None, |first, time| Some(first.map_or(time, |first: Scalar| first.min(time))))
+  }
+
+  pub fn last_key_time(&self) -> Option<Scalar> {
+    self.tracks.iter()
+      .filter_map(|track| track.time.values())
+      .filter_map(|key| key.floats.last().cloned())
+      .fold(None, |last, time| Some(last.map_or(time, |last: Scalar| last.max(time))))
+  }
+
+  // The begin and end of the animation, filling in missing values from the keys
+  pub fn range(&self) -> (Scalar, Scalar) {
+    let begin = self.begin.or_else(|| self.first_key_time()).unwrap_or(0.0);
+    let end = self.end.or_else(|| self.last_key_time()).unwrap_or(begin);
+    (begin, end)
+  }
+
+  // The node's local transform at a time in seconds, clamped to the
+  // animation's range. Tracks replace the value of the transform in the
+  // node they target, the other transforms keep their values.
+  pub fn sample(&self, node: &Node, seconds: Scalar) -> Matrix4<Scalar> {
+    let time = self.file_time(seconds);
+
+    let mut transforms = node.transforms.clone();
+    for track in &self.tracks {
+      if let Some(transform) = transforms.iter_mut().find(|transform| !transform.name.is_empty() && transform.name == track.target_ref) {
+        if let Some(value) = track.sample_value(time) {
+          transform.value.set(value);
+        }
+      }
+    }
+
+    transforms.iter()
+      .filter(|transform| !transform.object)
+      .fold(Matrix4::identity(), |matrix, transform| matrix*transform.matrix())
+  }
+
+  // The node's morph weights at a time in seconds, the same way as sample
+  pub fn sample_morph_weights(&self, node: &Node, seconds: Scalar) -> Vec<MorphWeight> {
+    let time = self.file_time(seconds);
+    let mut weights = match node.geometry() {
+      Some(geometry) => geometry.morph_weights.clone(),
+      None => return Vec::new(),
+    };
+    for track in &self.tracks {
+      if let Some(weight) = weights.iter_mut().find(|weight| !weight.name.is_empty() && weight.name == track.target_ref) {
+        if let Some(TrackValue::Float(value)) = track.sample_value(time) {
+          weight.weight = value;
+        }
+      }
+    }
+    weights
+  }
+
+  fn file_time(&self, seconds: Scalar) -> Scalar {
+    let (begin, end) = self.range();
+    let mut time = seconds/self.time_scale;
+    if time < begin {
+      time = begin;
+    }
+    if time > end {
+      time = end;
+    }
+    time
+  }
+}
+
+impl Default for Animation {
+  fn default() -> Animation {
+    Animation::new()
+  }
+}
@@ -0,0 +1,298 @@
+use std::convert::TryFrom;
+
+use cgmath::Matrix;
+use cgmath::Matrix3;
+use cgmath::Matrix4;
+use cgmath::Vector3;
+use cgmath::InnerSpace;
+use cgmath::SquareMatrix;
+
+use animation::{Animation, KeyType, TargetType, Track};
+use options::{Axis, ConversionOptions, Handedness, Units};
+use scalar::Scalar;
+use scene::Node;
+use skin::Skin;
+use transform::{Rotation, Scale, TransformValue, Translation};
+use {matrix_from_floats, GeometryObject, Metric};
+
+// The columns are the right, up and forward directions
+fn basis(up: Axis, forward: Axis, handedness: Handedness) -> Option<Matrix3<Scalar>> {
+  let up = up.vector();
+  let forward = forward.vector();
+  let mut right = forward.cross(up);
+  if right.magnitude2() == 0.0 {
+    return None;
+  }
+  if handedness == Handedness::Left {
+    right = -right;
+  }
+  Some(Matrix3::from_cols(right, up, forward))
+}
+
+// Moves a model from the file's axes and units to the ones asked for. The
+// matrix only swaps and negates axes, and mirrors the model when the
+// handedness changes.
+pub(crate) struct Conversion {
+  matrix: Matrix3<Scalar>,
+  // What distances, angles and times are multiplied by
+  distance: Scalar,
+  angle: Scalar,
+  time: Scalar,
+  // The seconds per unit of time once converted
+  time_scale: Scalar,
+}
+
+impl Conversion {
+  // None when either up and forward are the same axis
+  pub fn new(up: Axis, forward: Axis, target: &ConversionOptions) -> Option<Conversion> {
+    let source = basis(up, forward, Handedness::Right)?;
+    let target = basis(target.up, target.forward, target.handedness)?;
+    Some(Conversion {
+      matrix: target*source.transpose(),
+      distance: 1.0,
+      angle: 1.0,
+      time: 1.0,
+      time_scale: 1.0,
+    })
+  }
+
+  // Converts from the file's units to the ones asked for, or keeps the
+  // file's distances and times without any. Angles always become radians.
+  pub fn with_units(mut self, metric: &Metric, units: Option<&Units>) -> Conversion {
+    self.angle = metric.angle;
+    self.time_scale = metric.time;
+    if let Some(units) = units {
+      self.distance = metric.distance/units.distance;
+      self.time = metric.time/units.time;
+      self.time_scale = units.time;
+    }
+    self
+  }
+
+  // Whether triangles have to be rewound to keep facing the same way
+  pub fn mirrors(&self) -> bool {
+    self.matrix.determinant() < 0.0
+  }
+
+  pub fn direction(&self, direction: [Scalar; 3]) -> [Scalar; 3] {
+    (self.matrix*Vector3::from(direction)).into()
+  }
+
+  pub fn position(&self, position: [Scalar; 3]) -> [Scalar; 3] {
+    (self.matrix*Vector3::from(position)*self.distance).into()
+  }
+
+  fn directions(&self, directions: &mut [[Scalar; 3]]) {
+    for direction in directions {
+      *direction = self.direction(*direction);
+    }
+  }
+
+  fn positions(&self, positions: &mut [[Scalar; 3]]) {
+    for position in positions {
+      *position = self.position(*position);
+    }
+  }
+
+  // Scales move to the new axes but keep their sign
+  fn scale(&self, scale: [Scalar; 3]) -> [Scalar; 3] {
+    let mut converted = [0.0; 3];
+    for (axis, &value) in scale.iter().enumerate() {
+      converted[self.axis(axis).0] = value;
+    }
+    converted
+  }
+
+  // Only the translation of a transform is a distance
+  pub fn matrix(&self, matrix: &Matrix4<Scalar>) -> Matrix4<Scalar> {
+    let conversion = Matrix4::from(self.matrix);
+    let mut converted = conversion*matrix*conversion.transpose();
+    converted.w.x *= self.distance;
+    converted.w.y *= self.distance;
+    converted.w.z *= self.distance;
+    converted
+  }
+
+  // Which axis the x, y or z axis becomes and whether it's negated
+  fn axis(&self, axis: usize) -> (usize, Scalar) {
+    let column = self.matrix[axis];
+    let index = (0..3).find(|&i| column[i] != 0.0).unwrap_or(axis);
+    (index, column[index])
+  }
+
+  // Rotations turn the other way in a mirrored model
+  fn angle_sign(&self) -> Scalar {
+    if self.mirrors() { -1.0 } else { 1.0 }
+  }
+
+  // What a single axis translation or rotation is multiplied by, which
+  // negates it when its axis is flipped. Scales keep their value.
+  fn single_factor(&self, value: &TransformValue) -> Scalar {
+    match (*value, single_axis(value)) {
+      (TransformValue::Translation(_), Some(axis)) => self.axis(axis).1*self.distance,
+      (TransformValue::Rotation(_), Some(axis)) => self.axis(axis).1*self.angle_sign()*self.angle,
+      _ => 1.0,
+    }
+  }
+
+  pub fn transform_value(&self, value: TransformValue) -> TransformValue {
+    let index = single_axis(&value).map_or(0, |axis| self.axis(axis).0);
+    let factor = self.single_factor(&value);
+
+    match value {
+      TransformValue::Transform(matrix) => TransformValue::Transform(self.matrix(&matrix)),
+      TransformValue::Translation(Translation::X(offset)) |
+      TransformValue::Translation(Translation::Y(offset)) |
+      TransformValue::Translation(Translation::Z(offset)) => {
+        let translation = [Translation::X, Translation::Y, Translation::Z][index];
+        TransformValue::Translation(translation(factor*offset))
+      },
+      TransformValue::Translation(Translation::Xyz(offset)) => {
+        TransformValue::Translation(Translation::Xyz(self.position(offset.into()).into()))
+      },
+      TransformValue::Rotation(Rotation::X(angle)) |
+      TransformValue::Rotation(Rotation::Y(angle)) |
+      TransformValue::Rotation(Rotation::Z(angle)) => {
+        let rotation = [Rotation::X, Rotation::Y, Rotation::Z][index];
+        TransformValue::Rotation(rotation(factor*angle))
+      },
+      TransformValue::Rotation(Rotation::Axis(angle, axis)) => {
+        TransformValue::Rotation(Rotation::Axis(self.angle_sign()*self.angle*angle, self.direction(axis.into()).into()))
+      },
+      TransformValue::Rotation(Rotation::Quaternion(mut quaternion)) => {
+        quaternion.v = self.matrix*quaternion.v*self.angle_sign();
+        TransformValue::Rotation(Rotation::Quaternion(quaternion))
+      },
+      TransformValue::Scale(Scale::X(scale)) |
+      TransformValue::Scale(Scale::Y(scale)) |
+      TransformValue::Scale(Scale::Z(scale)) => {
+        let axis_scale = [Scale::X, Scale::Y, Scale::Z][index];
+        TransformValue::Scale(axis_scale(scale))
+      },
+      TransformValue::Scale(Scale::Xyz(scale)) => TransformValue::Scale(Scale::Xyz(self.scale(scale.into()).into())),
+    }
+  }
+
+  // Converts the value and control keys of a track the same way as the
+  // transform it targets, given the target before conversion
+  fn track(&self, track: &mut Track, original: &TransformValue) {
+    let target = TargetType::from_transform(original);
+    let stride = target.components().unwrap_or(1);
+    let value_keys = track.value.keys.iter_mut()
+      .filter(|key| key.is_value() || key.key_type == KeyType::PlusControl || key.key_type == KeyType::MinusControl);
+    for key in value_keys {
+      for value in key.floats.chunks_mut(stride).filter(|value| value.len() == stride) {
+        match target {
+          TargetType::Xpos | TargetType::Ypos | TargetType::Zpos |
+          TargetType::Xrot | TargetType::Yrot | TargetType::Zrot |
+          TargetType::Xscale | TargetType::Yscale | TargetType::Zscale => {
+            value[0] *= self.single_factor(original);
+          },
+          TargetType::Xyzpos => {
+            let converted = self.position([value[0], value[1], value[2]]);
+            value.copy_from_slice(&converted);
+          },
+          TargetType::Xyzscale => {
+            let converted = self.scale([value[0], value[1], value[2]]);
+            value.copy_from_slice(&converted);
+          },
+          TargetType::AxisRot => {
+            let axis = self.direction([value[1], value[2], value[3]]);
+            value[0] *= self.angle_sign()*self.angle;
+            value[1..].copy_from_slice(&axis);
+          },
+          TargetType::QuaternionRot => {
+            let axis = self.direction([value[0], value[1], value[2]]);
+            for (value, axis) in value.iter_mut().zip(&axis) {
+              *value = axis*self.angle_sign();
+            }
+          },
+          TargetType::Transform => {
+            if let Ok(floats) = <[Scalar; 16]>::try_from(&*value) {
+              let matrix = self.matrix(&matrix_from_floats(&floats));
+              let converted: &[Scalar; 16] = matrix.as_ref();
+              value.copy_from_slice(converted);
+            }
+          },
+          TargetType::MorphWeight(_) | TargetType::Unknown => {},
+        }
+      }
+    }
+    track.target = TargetType::from_transform(&self.transform_value(*original));
+  }
+
+  // Converts the node's transforms and the animation tracks targeting them,
+  // then its subnodes. World transforms need updating afterwards.
+  pub fn node(&self, node: &mut Node) {
+    for animation in &mut node.animations {
+      self.animation_times(animation);
+    }
+    for transform in &mut node.transforms {
+      if !transform.name.is_empty() {
+        for animation in &mut node.animations {
+          for track in animation.tracks.iter_mut().filter(|track| track.target_ref == transform.name) {
+            self.track(track, &transform.value);
+          }
+        }
+      }
+      transform.value = self.transform_value(transform.value);
+    }
+    node.update_local_transform();
+    for child in &mut node.children {
+      self.node(child);
+    }
+  }
+
+  fn animation_times(&self, animation: &mut Animation) {
+    animation.begin = animation.begin.map(|begin| begin*self.time);
+    animation.end = animation.end.map(|end| end*self.time);
+    animation.time_scale = self.time_scale;
+    for key in animation.tracks.iter_mut().flat_map(|track| &mut track.time.keys) {
+      for time in &mut key.floats {
+        *time *= self.time;
+      }
+    }
+  }
+
+  pub fn geometry_object(&self, object: &mut GeometryObject) {
+    self.positions(&mut object.vertex);
+    self.directions(&mut object.normal);
+    for &mut (_, ref mut positions) in &mut object.morph_vertex {
+      self.positions(positions);
+    }
+    for &mut (_, ref mut normals) in &mut object.morph_normal {
+      self.directions(normals);
+    }
+    if let Some(ref mut skin) = object.skin {
+      self.skin(skin);
+    }
+    if self.mirrors() {
+      for triangle in object.index.chunks_mut(3) {
+        triangle.swap(1, 2);
+      }
+    }
+  }
+
+  fn skin(&self, skin: &mut Skin) {
+    skin.bind_transform = self.matrix(&skin.bind_transform);
+    for transform in &mut skin.skeleton.bind_transforms {
+      *transform = self.matrix(transform);
+    }
+  }
+}
+
+// The axis of a translation, rotation or scale along x, y or z
+fn single_axis(value: &TransformValue) -> Option<usize> {
+  match *value {
+    TransformValue::Translation(Translation::X(_)) |
+    TransformValue::Rotation(Rotation::X(_)) |
+    TransformValue::Scale(Scale::X(_)) => Some(0),
+    TransformValue::Translation(Translation::Y(_)) |
+    TransformValue::Rotation(Rotation::Y(_)) |
+    TransformValue::Scale(Scale::Y(_)) => Some(1),
+    TransformValue::Translation(Translation::Z(_)) |
+    TransformValue::Rotation(Rotation::Z(_)) |
+    TransformValue::Scale(Scale::Z(_)) => Some(2),
+    _ => None,
+  }
+}
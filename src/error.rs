@@ -0,0 +1,165 @@
+use std::fmt;
+use std::io;
+use std::error::Error;
+use std::path::PathBuf;
+
+use openddl::{ResolveError, SyntaxError};
+
+// line and column are 1 based, and 0 when the error isn't tied to a
+// position in the file, like failing to open it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+  pub path: Option<PathBuf>,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Location {
+  pub fn new(line: usize, column: usize) -> Location {
+    Location {
+      path: None,
+      line,
+      column,
+    }
+  }
+}
+
+impl fmt::Display for Location {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if let Some(ref path) = self.path {
+      write!(f, "{}", path.display())?;
+    } else {
+      write!(f, "<memory>")?;
+    }
+    if self.line > 0 {
+      write!(f, ":{}:{}", self.line, self.column)?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+  Io {
+    location: Location,
+    error: io::Error,
+  },
+  Syntax {
+    location: Location,
+    message: String,
+  },
+  UnresolvedReference {
+    location: Location,
+    reference: String,
+  },
+  DuplicateName {
+    location: Location,
+    name: String,
+  },
+  TypeMismatch {
+    location: Location,
+    expected: String,
+    found: String,
+  },
+  InvalidValue {
+    location: Location,
+    message: String,
+  },
+  // Something a lenient load would have skipped with a warning
+  Unsupported {
+    location: Location,
+    message: String,
+  },
+}
+
+impl ParseError {
+  pub fn location(&self) -> &Location {
+    match *self {
+      ParseError::Io { ref location, .. } |
+      ParseError::Syntax { ref location, .. } |
+      ParseError::UnresolvedReference { ref location, .. } |
+      ParseError::DuplicateName { ref location, .. } |
+      ParseError::TypeMismatch { ref location, .. } |
+      ParseError::InvalidValue { ref location, .. } |
+      ParseError::Unsupported { ref location, .. } => location,
+    }
+  }
+
+  pub(crate) fn with_path(mut self, path: PathBuf) -> ParseError {
+    match self {
+      ParseError::Io { ref mut location, .. } |
+      ParseError::Syntax { ref mut location, .. } |
+      ParseError::UnresolvedReference { ref mut location, .. } |
+      ParseError::DuplicateName { ref mut location, .. } |
+      ParseError::TypeMismatch { ref mut location, .. } |
+      ParseError::InvalidValue { ref mut location, .. } |
+      ParseError::Unsupported { ref mut location, .. } => location.path = Some(path),
+    }
+    self
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ParseError::Io { ref location, ref error } => write!(f, "{}: {}", location, error),
+      ParseError::Syntax { ref location, ref message } => write!(f, "{}: {}", location, message),
+      ParseError::UnresolvedReference { ref location, ref reference } => {
+        write!(f, "{}: Unresolved reference {}", location, reference)
+      },
+      ParseError::DuplicateName { ref location, ref name } => write!(f, "{}: Duplicate name {}", location, name),
+      ParseError::TypeMismatch { ref location, ref expected, ref found } => {
+        write!(f, "{}: Expected {} but found {}", location, expected, found)
+      },
+      ParseError::InvalidValue { ref location, ref message } |
+      ParseError::Unsupported { ref location, ref message } => write!(f, "{}: {}", location, message),
+    }
+  }
+}
+
+impl Error for ParseError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match *self {
+      ParseError::Io { ref error, .. } => Some(error),
+      _ => None,
+    }
+  }
+}
+
+// Something in the file that was skipped because the crate doesn't support
+// it, or couldn't make sense of it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+  pub location: Location,
+  pub message: String,
+}
+
+impl fmt::Display for Warning {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.location, self.message)
+  }
+}
+
+impl From<SyntaxError> for ParseError {
+  fn from(error: SyntaxError) -> ParseError {
+    ParseError::Syntax {
+      location: Location::new(error.line, error.column),
+      message: error.message,
+    }
+  }
+}
+
+impl From<ResolveError> for ParseError {
+  fn from(error: ResolveError) -> ParseError {
+    match error {
+      ResolveError::Duplicate { name, line, column } => ParseError::DuplicateName {
+        location: Location::new(line, column),
+        name,
+      },
+      ResolveError::Unresolved { reference, line, column } => ParseError::UnresolvedReference {
+        location: Location::new(line, column),
+        reference,
+      },
+    }
+  }
+}
@@ -0,0 +1,86 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use error::ParseError;
+use openddl::Structure;
+
+type Handler = Arc<dyn Fn(&Structure) -> Result<Arc<dyn Any + Send + Sync>, ParseError> + Send + Sync>;
+
+// The result of a registered handler for an Extension structure
+#[derive(Clone)]
+pub struct Extension {
+  pub applic: String,
+  pub kind: String,
+  value: Arc<dyn Any + Send + Sync>,
+}
+
+impl Extension {
+  // The handler's result, None if it returned a different type
+  pub fn get<T: Any>(&self) -> Option<&T> {
+    self.value.downcast_ref::<T>()
+  }
+}
+
+impl fmt::Debug for Extension {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Extension")
+      .field("applic", &self.applic)
+      .field("kind", &self.kind)
+      .finish()
+  }
+}
+
+// What an Extension structure is in, with the owner's name for those other
+// than the file itself, like $geometry1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Owner<'a> {
+  File,
+  Node(&'a str),
+  GeometryObject(&'a str),
+  Material(&'a str),
+}
+
+// Handlers for Extension structures, keyed by their applic and type
+// properties. Extensions without a handler are skipped with a warning, or
+// fail a strict load.
+#[derive(Clone)]
+pub struct ExtensionRegistry {
+  handlers: Vec<(String, String, Handler)>,
+}
+
+impl ExtensionRegistry {
+  pub fn new() -> ExtensionRegistry {
+    ExtensionRegistry {
+      handlers: Vec::new(),
+    }
+  }
+
+  // The handler is given the Extension structure itself, its data is in
+  // the substructures
+  pub fn register<T, F>(&mut self, applic: &str, kind: &str, handler: F)
+    where T: Any + Send + Sync, F: Fn(&Structure) -> Result<T, ParseError> + Send + Sync + 'static {
+    let handler: Handler = Arc::new(move |structure| {
+      handler(structure).map(|value| Arc::new(value) as Arc<dyn Any + Send + Sync>)
+    });
+    self.handlers.push((applic.to_string(), kind.to_string(), handler));
+  }
+
+  pub(crate) fn read(&self, applic: &str, kind: &str, structure: &Structure) -> Result<Option<Extension>, ParseError> {
+    let handler = match self.handlers.iter().find(|handler| handler.0 == applic && handler.1 == kind) {
+      Some(handler) => &handler.2,
+      None => return Ok(None),
+    };
+    Ok(Some(Extension {
+      applic: applic.to_string(),
+      kind: kind.to_string(),
+      value: handler(structure)?,
+    }))
+  }
+}
+
+impl Default for ExtensionRegistry {
+  fn default() -> ExtensionRegistry {
+    ExtensionRegistry::new()
+  }
+}
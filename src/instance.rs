@@ -0,0 +1,41 @@
+use cgmath::Matrix4;
+
+use extension::Extension;
+use morph::Morph;
+use scalar::Scalar;
+use scene::{MaterialRef, MorphWeight};
+use skin::Skin;
+
+// A GeometryObject's mesh in object space, shared by every node that
+// refers to it
+#[derive(Clone, Debug)]
+pub struct Mesh {
+  // The GeometryObject's name, like $geometry1
+  pub object_ref: String,
+  pub positions: Vec<[Scalar; 3]>,
+  pub normals: Vec<[Scalar; 3]>,
+  pub texcoords: Vec<[Scalar; 2]>,
+  pub indices: Vec<u32>,
+  pub skin: Option<Skin>,
+  pub morphs: Vec<Morph>,
+  // The GeometryObject's extensions
+  pub extensions: Vec<Extension>,
+}
+
+// A GeometryNode drawing one of the meshes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+  // The node's structure name, like $node1
+  pub node_ref: String,
+  // An index into the meshes
+  pub mesh: usize,
+  // The node's transforms relative to its parent, and the product of its
+  // ancestors' and its own
+  pub local_transform: Matrix4<Scalar>,
+  pub world_transform: Matrix4<Scalar>,
+  // The node's world transform followed by its object only transforms,
+  // taking the mesh from object space to world space
+  pub transform: Matrix4<Scalar>,
+  pub material_refs: Vec<MaterialRef>,
+  pub morph_weights: Vec<MorphWeight>,
+}
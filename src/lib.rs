@@ -1,60 +1,113 @@
-extern crate cgmath;
+pub extern crate cgmath;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+pub mod openddl;
+mod error;
+mod scene;
+mod transform;
+mod animation;
+mod skin;
+mod morph;
+mod extension;
+mod scalar;
+mod options;
+mod convert;
+mod instance;
+mod validate;
 
 use std::str;
+use std::str::FromStr;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::Read;
+use std::path::Path;
+use std::borrow::Cow;
+use std::convert::TryFrom;
 
 use cgmath::Vector4;
+use cgmath::Matrix3;
 use cgmath::Matrix4;
+use cgmath::Vector3;
+use cgmath::Quaternion;
+use cgmath::Matrix;
+use cgmath::InnerSpace;
 use cgmath::SquareMatrix;
 
-const X: &str = "\"x\"";
-const Y: &str = "\"y\"";
-const Z: &str = "\"z\"";
-
-const PLAINSTRING: &str = "string";
-const STRING: &str = "{string";
-const REF: &str = "{ref";
-const FLOAT: &str = "{float";
-const INDEX: &str = "(index";
-const PRIMITIVE: &str = "(primitive";
-const ATTRIB: &str = "(attrib";
-const POSITION: &str = "\"position\")";
-const NORMAL: &str = "\"normal\")";
-const DIFFUSE: &str = "\"diffuse\")";
-const SPECULAR: &str = "\"specular\")";
-const TEXCOORD: &str = "\"texcoord\")";
-
-const FLOAT2: &str = "float[2]";
-const FLOAT3: &str = "float[3]";
-const FLOAT16: &str = "float[16]";
-const UNSIGNED_INT3: &str = "unsigned_int32[3]";
+use openddl::{Document, Literal, Name, Reference, Resolver, Structure};
+use scalar::{scalar, scalars};
+use convert::Conversion;
+
+pub use error::{Location, ParseError, Warning};
+pub use animation::{Animation, Track, TrackValue, Time, Value, Key, Curve, KeyType, TargetType};
+pub use transform::{NodeTransform, TransformValue, Translation, Rotation, Scale};
+pub use skin::{Skin, Skeleton, BoneWeight};
+pub use morph::Morph;
+pub use instance::{Mesh, Instance};
+pub use scalar::{Scalar, ToF32};
+pub use extension::{Extension, ExtensionRegistry, Owner};
+pub use options::{ParseOptions, ConversionOptions, Axis, Handedness, Units};
+pub use validate::{validate, Violation};
+pub use scene::{Scene, Node, NodeKind, NodeIter, GeometryNode, LightNode, CameraNode, BoneNode, MaterialRef, MorphWeight};
+
+const X: &str = "x";
+const Y: &str = "y";
+const Z: &str = "z";
+const XYZ: &str = "xyz";
+const AXIS: &str = "axis";
+const QUATERNION: &str = "quaternion";
+const OBJECT: &str = "object";
+
+const KEY: &str = "key";
+const INDEX: &str = "index";
+const ATTRIB: &str = "attrib";
+const MORPH_INDEX: &str = "morph";
+const BASE: &str = "base";
+const POSITION: &str = "position";
+const NORMAL: &str = "normal";
+const DIFFUSE: &str = "diffuse";
+const SPECULAR: &str = "specular";
+const TEXCOORD: &str = "texcoord";
 
 const METRIC: &str = "Metric";
-const KEY: &str = "(key";
-const EQUALS: &str = "=";
-
-const DISTANCE: &str = "\"distance\")";
-const ANGLE: &str = "\"angle\")";
-const TIME: &str = "\"time\")";
-const UP: &str = "\"up\")";
-const FORWARD: &str = "\"forward\")";
+const DISTANCE: &str = "distance";
+const ANGLE: &str = "angle";
+const TIME: &str = "time";
+const UP: &str = "up";
+const FORWARD: &str = "forward";
 
+const NODE: &str = "Node";
 const GEOMETRY_NODE: &str = "GeometryNode";
+const LIGHT_NODE: &str = "LightNode";
+const CAMERA_NODE: &str = "CameraNode";
+const BONE_NODE: &str = "BoneNode";
 const GEOMETRY_OBJECT: &str = "GeometryObject";
-const OPEN_BRACKET: &str = "{";
-const CLOSE_BRACKET: &str = "}";
-
-const ZERO_BRACKET: &str = "0)";
+const LIGHT_OBJECT: &str = "LightObject";
+const CAMERA_OBJECT: &str = "CameraObject";
 
 const NAME: &str = "Name";
 const OBJECT_REF: &str = "ObjectRef";
 const MATERIAL_REF: &str = "MaterialRef";
 const TRANSFORM: &str = "Transform";
+const TRANSLATION: &str = "Translation";
+const ROTATION: &str = "Rotation";
+const SCALE: &str = "Scale";
+const MORPH_WEIGHT: &str = "MorphWeight";
 
 const MESH: &str = "Mesh";
+const PRIMITIVE: &str = "primitive";
+const TRIANGLES: &str = "triangles";
 const VERTEXARRAY: &str = "VertexArray";
 const INDEXARRAY: &str = "IndexArray";
+const SKIN: &str = "Skin";
+const SKELETON: &str = "Skeleton";
+const BONE_REF_ARRAY: &str = "BoneRefArray";
+const BONE_COUNT_ARRAY: &str = "BoneCountArray";
+const BONE_INDEX_ARRAY: &str = "BoneIndexArray";
+const BONE_WEIGHT_ARRAY: &str = "BoneWeightArray";
+const MORPH: &str = "Morph";
+const EXTENSION: &str = "Extension";
+const APPLIC: &str = "applic";
+const TYPE: &str = "type";
 
 const MATERIAL: &str = "Material";
 const TEXTURE: &str = "Texture";
@@ -66,138 +119,263 @@ const TRACK: &str = "Track";
 const TRACK_KEY: &str = "Key";
 const TRACK_TIME: &str = "Time";
 const VALUE: &str = "Value";
-const CURVE: &str = "(curve";
-const KIND: &str = "(kind";
-const PLUSCONTROL: &str = "\"+control\")";
-const MINUSCONTROL: &str = "\"-control\")";
-const TARGET: &str = "(target";
-const XPOS: &str = "%xpos)";
-const YPOS: &str = "%ypos)";
-const ZPOS: &str = "%zpos)";
-const XROT: &str = "%xrot)";
-const YROT: &str = "%yrot)";
-const ZROT: &str = "%zrot)";
-const BEZIER: &str = "\"bezier\")";
-const LINEAR: &str = "\"linear\")";
-
-const BEGIN: &str = "(begin";
+const CURVE: &str = "curve";
+const KIND: &str = "kind";
+const PLUSCONTROL: &str = "+control";
+const MINUSCONTROL: &str = "-control";
+const TARGET: &str = "target";
+const BEZIER: &str = "bezier";
+const LINEAR: &str = "linear";
+const TCB: &str = "tcb";
+const TENSION: &str = "tension";
+const CONTINUITY: &str = "continuity";
+const BIAS: &str = "bias";
+
+const CLIP: &str = "clip";
+const BEGIN: &str = "begin";
 const END: &str = "end";
 
-fn get_raw_float(v: &str) -> Option<f32> {
-  let mut result = None;
-  
-  if let Ok(float) = v.parse::<f32>() {
-    result = Some(float);
+fn location(structure: &Structure) -> Location {
+  Location::new(structure.line, structure.column)
+}
+
+fn type_mismatch(structure: &Structure, expected: &str) -> ParseError {
+  let (location, found) = match structure.primitive_structure() {
+    Some(primitive) => (location(primitive), primitive.data.as_ref().map(|data| data.type_name()).unwrap_or_default()),
+    None => (location(structure), "no data".to_string()),
+  };
+  ParseError::TypeMismatch {
+    location,
+    expected: format!("{} in {}", expected, structure.identifier),
+    found,
   }
-  result
 }
 
-fn get_float(v: Vec<&str>) -> Option<f32> {
-  let mut result = None;
-  
-  let v: Vec<&str> = v[0].split("{").collect();
-  let v: Vec<&str> = v[1].split("}").collect();
-  if let Ok(float) = v[0].parse::<f32>() {
-    result = Some(float);
+// What a lenient load skipped. A strict load fails on the first one instead.
+struct Warnings {
+  strict: bool,
+  warnings: Vec<Warning>,
+}
+
+impl Warnings {
+  fn new(strict: bool) -> Warnings {
+    Warnings {
+      strict,
+      warnings: Vec::new(),
+    }
   }
   
-  result
+  fn report(&mut self, structure: &Structure, message: String) -> Result<(), ParseError> {
+    if self.strict {
+      return Err(ParseError::Unsupported { location: location(structure), message });
+    }
+    self.warnings.push(Warning { location: location(structure), message });
+    Ok(())
+  }
 }
 
-fn get_string_value(v: Vec<&str>) -> Option<&str> {
-  let v: Vec<&str> = v[0].split("{").collect();
-  let v: Vec<&str> = v[1].split("}").collect();
-  let result = Some(v[0]);
-  
-  result
+// The properties that are read, by structure. Structures that aren't
+// listed have none that are.
+const LOADED_PROPERTIES: &[(&str, &[&str])] = &[
+  (METRIC, &[KEY]),
+  (MATERIAL_REF, &[INDEX]),
+  (TRANSFORM, &[OBJECT]),
+  (TRANSLATION, &[KIND, OBJECT]),
+  (ROTATION, &[KIND, OBJECT]),
+  (SCALE, &[KIND, OBJECT]),
+  (MORPH_WEIGHT, &[INDEX]),
+  (MESH, &[PRIMITIVE]),
+  (VERTEXARRAY, &[ATTRIB, MORPH_INDEX]),
+  (MORPH, &[INDEX, BASE]),
+  (COLOUR, &[ATTRIB]),
+  (TEXTURE, &[ATTRIB]),
+  (EXTENSION, &[APPLIC, TYPE]),
+  (ANIMATION, &[CLIP, BEGIN, END]),
+  (TRACK, &[TARGET]),
+  (TRACK_TIME, &[CURVE]),
+  (VALUE, &[CURVE]),
+  (TRACK_KEY, &[KIND]),
+];
+
+// Reports the properties of a structure that aren't read, whether they're
+// unknown or just not supported
+fn check_properties(structure: &Structure, warnings: &mut Warnings) -> Result<(), ParseError> {
+  let identifier = structure.identifier.as_str();
+  let loaded = LOADED_PROPERTIES.iter().find(|&&(loaded, _)| loaded == identifier).map_or(&[][..], |&(_, keys)| keys);
+  for property in &structure.properties {
+    let key = property.key.as_str();
+    if loaded.contains(&key) {
+      continue;
+    }
+    let message = if validate::is_opengex_property(identifier, key) {
+      format!("{} property {} is not supported", identifier, key)
+    } else {
+      format!("Unknown property {} in {}", key, identifier)
+    };
+    warnings.report(structure, message)?;
+  }
+  Ok(())
+}
+
+// Reports the derived structures a reader doesn't know about, and the
+// properties of the ones it does that it doesn't read. Primitive
+// structures hold their parent's data, and are checked when it's read.
+fn check_children(structures: &[Structure], parent: Option<&Structure>, known: &[&str], warnings: &mut Warnings) -> Result<(), ParseError> {
+  for structure in structures.iter().filter(|structure| !structure.is_primitive()) {
+    let identifier = structure.identifier.as_str();
+    if known.contains(&identifier) {
+      check_properties(structure, warnings)?;
+      continue;
+    }
+    let message = match (validate::is_opengex_structure(identifier), parent) {
+      (true, Some(parent)) => format!("{} in {} is not supported", identifier, parent.identifier),
+      (true, None) => format!("{} is not supported", identifier),
+      (false, Some(parent)) => format!("Unknown structure {} in {}", identifier, parent.identifier),
+      (false, None) => format!("Unknown structure {}", identifier),
+    };
+    warnings.report(structure, message)?;
+  }
+  Ok(())
 }
 
-fn remove_brackets(v: &str) -> &str {
-  let v = v.trim_matches('{');
-  let v = v.trim_matches('}');
-  let v = v.trim_matches(')');
-  let v = v.trim_matches('(');
-  let v = v.trim_matches('\"');
-  let v = v.trim_matches('/');
-  let v = v.trim_matches(',');
-  let v = v.trim_matches('}');
-  let v = v.trim();
-  v
+// An unsigned integer property like index, reporting values of the wrong type
+fn get_index(structure: &Structure, key: &str, warnings: &mut Warnings) -> Result<Option<u32>, ParseError> {
+  let value = match structure.property(key) {
+    Some(value) => value,
+    None => return Ok(None),
+  };
+  match value.as_i64().and_then(|int| u32::try_from(int).ok()) {
+    Some(index) => Ok(Some(index)),
+    None => {
+      warnings.report(structure, format!("Expected an unsigned integer for the {} of {}", key, structure.identifier))?;
+      Ok(None)
+    },
+  }
 }
 
-#[derive(Clone)]
-pub enum Attrib {
-  Diffuse,
-  Normal,
-  Specular,
-  Unknown,
+fn get_string(structure: &Structure) -> Result<&str, ParseError> {
+  structure.primitive()
+    .and_then(|data| data.strings())
+    .and_then(|strings| strings.first())
+    .map(|string| string.as_str())
+    .ok_or_else(|| type_mismatch(structure, "string"))
 }
 
-#[derive(Clone)]
-pub enum Curve {
-  Linear,
-  Bezier,
-  Unknown,
+// The path of the structure a reference refers to, which has to be the
+// kind of structure given. None for a null reference.
+fn resolve_reference<'a>(reference: &Reference, from: &'a Structure, resolver: &Resolver<'a>, kind: &str) -> Result<Option<String>, ParseError> {
+  let target = match resolver.resolve(reference, from)? {
+    Some(target) => target,
+    None => return Ok(None),
+  };
+  if target.identifier != kind {
+    return Err(ParseError::TypeMismatch {
+      location: location(from),
+      expected: format!("{} reference in {}", kind, from.identifier),
+      found: format!("{} {}", target.identifier, reference),
+    });
+  }
+  Ok(Some(resolver.path(target).unwrap_or_else(|| reference.to_string())))
 }
 
-#[derive(Clone)]
-pub enum KeyType {
-  Single,
-  Double,
-  Triple,
-  Quad,
-  Sixteen,
-  PlusControl,
-  MinusControl,
+fn get_reference<'a>(structure: &'a Structure, resolver: &Resolver<'a>, kind: &str) -> Result<Option<String>, ParseError> {
+  let reference = structure.primitive()
+    .and_then(|data| data.references())
+    .and_then(|references| references.first())
+    .ok_or_else(|| type_mismatch(structure, "ref"))?;
+  resolve_reference(reference, structure, resolver, kind)
+}
+
+fn invalid_value(structure: &Structure, message: String) -> ParseError {
+  ParseError::InvalidValue {
+    location: location(structure),
+    message,
+  }
+}
+
+// Any floating point data, converted to Scalar if it has to be. count is
+// the number of values, or subarrays when array_size isn't 0, that the
+// structure has to hold, None for any number.
+fn get_floats(structure: &Structure, array_size: usize, count: Option<usize>) -> Result<Cow<'_, [Scalar]>, ParseError> {
+  let expected = if array_size == 0 { "float".to_string() } else { format!("float[{}]", array_size) };
+  let data = match structure.primitive() {
+    Some(data) if data.array_size == array_size => data,
+    _ => return Err(type_mismatch(structure, &expected)),
+  };
+  let floats = scalars(data).ok_or_else(|| type_mismatch(structure, &expected))?;
+  match count {
+    Some(count) if floats.len() != count*array_size.max(1) => {
+      let found = floats.len()/array_size.max(1);
+      Err(invalid_value(structure, format!("Expected {} {} in {} but found {}", count, expected, structure.identifier, found)))
+    },
+    _ => Ok(floats),
+  }
+}
+
+// Exactly one subarray of N floats, like the matrix of a Transform
+fn get_array<const N: usize>(structure: &Structure) -> Result<[Scalar; N], ParseError> {
+  let floats = get_floats(structure, N, Some(1))?;
+  let mut array = [0.0; N];
+  array.copy_from_slice(&floats);
+  Ok(array)
+}
+
+// Any number of subarrays of N floats, like the positions of a VertexArray
+fn get_arrays<const N: usize>(structure: &Structure) -> Result<Vec<[Scalar; N]>, ParseError> {
+  let floats = get_floats(structure, N, None)?;
+  Ok(floats.chunks_exact(N).map(|chunk| {
+    let mut array = [0.0; N];
+    array.copy_from_slice(chunk);
+    array
+  }).collect())
+}
+
+fn get_float(structure: &Structure) -> Result<Scalar, ParseError> {
+  let floats = get_floats(structure, 0, Some(1))?;
+  floats.first().cloned().ok_or_else(|| type_mismatch(structure, "float"))
+}
+
+fn matrix_from_floats(raw: &[Scalar; 16]) -> Matrix4<Scalar> {
+  Matrix4::new(
+    raw[0], raw[1], raw[2], raw[3], 
+    raw[4], raw[5], raw[6], raw[7], 
+    raw[8], raw[9], raw[10], raw[11], 
+    raw[12], raw[13], raw[14], raw[15], 
+  )
+}
+
+fn get_attrib(structure: &Structure) -> Option<&str> {
+  structure.property(ATTRIB).and_then(|attrib| attrib.as_str())
 }
 
 #[derive(Clone)]
-pub enum TargetType {
-  Xpos,
-  Ypos,
-  Zpos,
-  Xrot,
-  Yrot,
-  Zrot,
+pub enum Attrib {
+  Diffuse,
+  Normal,
+  Specular,
   Unknown,
 }
 
 pub struct Normal {
-  pub normal: [f32; 3],
+  pub normal: [Scalar; 3],
 }
 
 pub struct Vertex {
-  pub vertex: [f32; 3],
+  pub vertex: [Scalar; 3],
 }
 
 pub struct Index {
-  index: u32,
+  pub index: u32,
 }
 
 pub struct UV {
-  pub uv: [f32; 2],
-}
-
-struct MaterialRef {
-  index: i32,
-  material_ref: String,
-}
-
-impl MaterialRef {
-  pub fn new() -> MaterialRef {
-    MaterialRef {
-      index: 0,
-      material_ref: "".to_string(),
-    }
-  }
+  pub uv: [Scalar; 2],
 }
 
 #[derive(Clone)]
 struct Texture {
   texture: String,
   attrib: Attrib,
-  
-  raw_transform: [f32; 16],
 }
 
 impl Texture {
@@ -205,36 +383,27 @@ impl Texture {
     Texture {
       texture: "".to_string(),
       attrib: Attrib::Unknown,
-      
-      raw_transform: [1.0, 0.0, 0.0, 0.0, 
-                      0.0, 1.0, 0.0, 0.0, 
-                      0.0, 0.0, 1.0, 0.0, 
-                      0.0, 0.0, 0.0, 1.0],
     }
   }
 }
 
 #[derive(Clone)]
 struct Material {
-  name: String,
   material_ref: String,
   textures: Vec<Texture>,
   
-  diffuse_colour: [f32; 3],
-  specular_colour: [f32; 3],
-  specular_power: f32,
+  diffuse_colour: [Scalar; 3],
+  extensions: Vec<Extension>,
 }
 
 impl Material {
   pub fn new() -> Material {
     Material {
-      name: "".to_string(),
       material_ref: "".to_string(),
       textures: Vec::new(),
       
       diffuse_colour: [0.0, 0.0, 0.0],
-      specular_colour: [0.0, 0.0, 0.0],
-      specular_power: 0.0,
+      extensions: Vec::new(),
     }
   }
 }
@@ -242,1072 +411,1058 @@ impl Material {
 #[derive(Clone)]
 struct GeometryObject {
   name: String,
-  mesh: String,
-  vertex: Vec<[f32; 3]>,
+  vertex: Vec<[Scalar; 3]>,
   index: Vec<u32>,
-  normal: Vec<[f32; 3]>,
-  texcoord: Vec<[f32; 2]>,
+  normal: Vec<[Scalar; 3]>,
+  texcoord: Vec<[Scalar; 2]>,
+  skin: Option<Skin>,
+  // Morph targets other than 0, the mesh itself, with their positions and
+  // normals filled in when the object is baked into a model
+  morphs: Vec<Morph>,
+  morph_vertex: Vec<(u32, Vec<[Scalar; 3]>)>,
+  morph_normal: Vec<(u32, Vec<[Scalar; 3]>)>,
+  extensions: Vec<Extension>,
 }
 
 impl GeometryObject {
   pub fn new() -> GeometryObject {
     GeometryObject {
       name: "".to_string(),
-      mesh: "".to_string(),
       vertex: Vec::new(),
       index: Vec::new(),
       normal: Vec::new(),
       texcoord: Vec::new(),
+      skin: None,
+      morphs: Vec::new(),
+      morph_vertex: Vec::new(),
+      morph_normal: Vec::new(),
+      extensions: Vec::new(),
     }
   }
 }
 
-struct GeometryNode {
-  name: String,
-  raw_transform: [f32; 16],
-  transform: Matrix4<f32>,
-  
-  object_ref: String,
-  
-  materialref: Vec<MaterialRef>, 
-  animation: Option<Animation>,
-}
-
-impl GeometryNode {
-  pub fn new(name: String) -> GeometryNode {
-    GeometryNode {
-      name: name,
-      raw_transform: [1.0, 0.0, 0.0, 0.0, 
-                      0.0, 1.0, 0.0, 0.0, 
-                      0.0, 0.0, 1.0, 0.0, 
-                      0.0, 0.0, 0.0, 1.0],
-      transform: Matrix4::identity(),
-      object_ref: "".to_string(),
-      
-      materialref: Vec::new(),
-      animation: None,
-    }
-  }
-}
-
-struct Metric {
-  distance: f32,
-  angle: f32,
-  time: f32,
-  up: String,
-  forward: String,
+// The units and axes of a file as its Metric structures give them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metric {
+  // Meters per unit of distance
+  pub distance: Scalar,
+  // Radians per unit of angle
+  pub angle: Scalar,
+  // Seconds per unit of time
+  pub time: Scalar,
+  pub up: Axis,
+  // None if the file doesn't give one
+  pub forward: Option<Axis>,
 }
 
 impl Metric {
   pub fn new() -> Metric {
     Metric {
       distance: 1.0,
-      angle: 1.0, 
+      angle: 1.0,
       time: 1.0,
-      up: Y.to_string(),
-      forward: "".to_string(),
+      up: Axis::Z,
+      forward: None,
+    }
+  }
+  
+  // Without a forward direction the model is only turned to make up point
+  // the way it's asked to
+  pub fn forward(&self) -> Axis {
+    match (self.forward, self.up) {
+      (Some(forward), _) => forward,
+      (None, Axis::Z) => Axis::Y,
+      (None, Axis::NegativeZ) => Axis::NegativeY,
+      (None, _) => Axis::NegativeZ,
     }
   }
 }
 
-#[derive(Clone)]
-struct IndexArray {
-  index: Vec<u32>,
+impl Default for Metric {
+  fn default() -> Metric {
+    Metric::new()
+  }
 }
 
 #[derive(Clone)]
-struct TexCoordArray {
-  texcoord: Vec<[f32; 2]>
+struct FinalModel {
+  vertices: Vec<[Scalar; 3]>,
+  indices: Vec<u32>,
+  normals: Vec<[Scalar; 3]>,
+  texcoords: Vec<[Scalar; 2]>,
+  skin: Option<Skin>,
+  morphs: Vec<Morph>,
+  morph_weights: Vec<MorphWeight>,
+  // Parsed but not exposed through the public api yet
+  #[allow(dead_code)]
+  material_ref: String,
 }
 
-#[derive(Clone)]
-struct NormalArray {
-  normal: Vec<[f32; 3]>,
+fn read_metric(structure: &Structure, metric: &mut Metric, warnings: &mut Warnings) -> Result<(), ParseError> {
+  match structure.property(KEY).and_then(|key| key.as_str()) {
+    Some(TIME) => metric.time = get_float(structure)?,
+    Some(key @ UP) | Some(key @ FORWARD) => {
+      let name = get_string(structure)?;
+      match Axis::from_name(name) {
+        Some(axis) if key == UP => metric.up = axis,
+        Some(axis) => metric.forward = Some(axis),
+        None => warnings.report(structure, format!("Unknown Metric {} direction {}", key, name))?,
+      }
+    },
+    Some(DISTANCE) => metric.distance = get_float(structure)?,
+    Some(ANGLE) => metric.angle = get_float(structure)?,
+    Some(key) => warnings.report(structure, format!("Unknown Metric key {}", key))?,
+    None => warnings.report(structure, "Metric without a key".to_string())?,
+  }
+  Ok(())
 }
 
-#[derive(Clone)]
-struct VertexArray {
-  attrib: Attrib,
-  morph_index: u32,
-  vertex: Vec<[f32; 3]>,
+fn read_curve(structure: &Structure, warnings: &mut Warnings) -> Result<Curve, ParseError> {
+  let curve = match structure.property(CURVE).and_then(|curve| curve.as_str()) {
+    None | Some(LINEAR) => Curve::Linear,
+    Some(BEZIER) => Curve::Bezier,
+    Some(TCB) => Curve::Tcb,
+    Some(curve) => {
+      warnings.report(structure, format!("Unknown curve {}", curve))?;
+      Curve::Unknown
+    },
+  };
+  Ok(curve)
 }
 
-#[derive(Clone)]
-struct Animation {
-  begin: f32,
-  end: f32,
-  tracks: Vec<Track>,
+fn read_keys(structure: &Structure, warnings: &mut Warnings) -> Result<Vec<Key>, ParseError> {
+  check_children(&structure.children, Some(structure), &[TRACK_KEY], warnings)?;
+  let mut keys = Vec::new();
+  for child in structure.children.iter().filter(|child| child.identifier == TRACK_KEY) {
+    let mut key = Key::new();
+    let data = child.primitive().ok_or_else(|| type_mismatch(child, "float"))?;
+    key.key_type = match data.array_size {
+      2 => KeyType::Double,
+      3 => KeyType::Triple,
+      4 => KeyType::Quad,
+      16 => KeyType::Sixteen,
+      _ => KeyType::Single,
+    };
+    key.floats = scalars(data).ok_or_else(|| type_mismatch(child, "float"))?.into_owned();
+    match child.property(KIND).and_then(|kind| kind.as_str()) {
+      Some(PLUSCONTROL) => key.key_type = KeyType::PlusControl,
+      Some(MINUSCONTROL) => key.key_type = KeyType::MinusControl,
+      Some(TENSION) => key.key_type = KeyType::Tension,
+      Some(CONTINUITY) => key.key_type = KeyType::Continuity,
+      Some(BIAS) => key.key_type = KeyType::Bias,
+      None | Some("value") => {},
+      Some(kind) => warnings.report(child, format!("Unknown Key kind {}", kind))?,
+    }
+    keys.push(key);
+  }
+  Ok(keys)
 }
 
-#[derive(Clone)]
-struct Track {
-  target: TargetType,
-  time: Time,
-  value: Value
+// targets are the node's transform and morph weight structures
+fn read_track<'a>(structure: &'a Structure, resolver: &Resolver<'a>, targets: &[(&Structure, TargetType)], warnings: &mut Warnings) -> Result<Track, ParseError> {
+  let mut track = Track::new();
+  
+  if let Some(target) = structure.property(TARGET).and_then(|target| target.as_reference()) {
+    let resolved = resolver.resolve(target, structure)?;
+    let found = resolved.and_then(|resolved| targets.iter().find(|target| std::ptr::eq(target.0, resolved)));
+    track.target = match found {
+      Some(&(_, target_type)) => target_type,
+      None => return Err(invalid_value(structure, format!("{} is not a transform or morph weight of the node", target))),
+    };
+    // Named the same way however the reference was written
+    if let Some(ref name) = resolved.and_then(|resolved| resolved.name.as_ref()) {
+      track.target_ref = name.to_string();
+    }
+  }
+  
+  check_children(&structure.children, Some(structure), &[TRACK_TIME, VALUE], warnings)?;
+  for child in &structure.children {
+    match child.identifier.as_str() {
+      TRACK_TIME => {
+        track.time.curve = read_curve(child, warnings)?;
+        track.time.keys = read_keys(child, warnings)?;
+      },
+      VALUE => {
+        track.value.curve = read_curve(child, warnings)?;
+        track.value.keys = read_keys(child, warnings)?;
+      },
+      _ => {},
+    }
+  }
+  
+  if let (Some(components), Some(values)) = (track.target.components(), track.value.values()) {
+    let found = match values.key_type {
+      KeyType::Single => 1,
+      KeyType::Double => 2,
+      KeyType::Triple => 3,
+      KeyType::Quad => 4,
+      _ => 16,
+    };
+    if found != components {
+      let value = structure.child(VALUE).unwrap_or(structure);
+      let expected = if components == 1 { "float".to_string() } else { format!("float[{}]", components) };
+      let found = if found == 1 { "float".to_string() } else { format!("float[{}]", found) };
+      return Err(ParseError::TypeMismatch { location: location(value), expected, found });
+    }
+  }
+  
+  Ok(track)
 }
 
-#[derive(Clone)]
-struct Time {
-  curve: Curve,
-  keys: Vec<Key>, // 1- 3 max
+fn read_animation<'a>(structure: &'a Structure, resolver: &Resolver<'a>, targets: &[(&Structure, TargetType)], metric: &Metric, warnings: &mut Warnings) -> Result<Animation, ParseError> {
+  let mut animation = Animation::new();
+  animation.time_scale = metric.time;
+  
+  if let Some(clip) = get_index(structure, CLIP, warnings)? {
+    animation.clip = clip;
+  }
+  animation.begin = structure.property(BEGIN).and_then(scalar);
+  animation.end = structure.property(END).and_then(scalar);
+  
+  check_children(&structure.children, Some(structure), &[TRACK], warnings)?;
+  for child in structure.children.iter().filter(|child| child.identifier == TRACK) {
+    animation.tracks.push(read_track(child, resolver, targets, warnings)?);
+  }
+  
+  Ok(animation)
 }
 
-#[derive(Clone)]
-struct Value {
-  curve: Curve,
-  keys: Vec<Key>, // 1- 4 max
+fn invalid_kind(structure: &Structure, kind: &str) -> ParseError {
+  ParseError::InvalidValue {
+    location: location(structure),
+    message: format!("Unknown kind \"{}\" for {}", kind, structure.identifier),
+  }
 }
 
-#[derive(Clone)]
-struct Key {
-  floats: Vec<f32>,
-  key_type: KeyType,
+fn read_transform(structure: &Structure, warnings: &mut Warnings) -> Result<NodeTransform, ParseError> {
+  let kind = structure.property(KIND).and_then(|kind| kind.as_str());
+  
+  let value = match structure.identifier.as_str() {
+    TRANSLATION => {
+      TransformValue::Translation(match kind {
+        Some(X) => Translation::X(get_float(structure)?),
+        Some(Y) => Translation::Y(get_float(structure)?),
+        Some(Z) => Translation::Z(get_float(structure)?),
+        None | Some(XYZ) => Translation::Xyz(Vector3::from(get_array::<3>(structure)?)),
+        Some(kind) => return Err(invalid_kind(structure, kind)),
+      })
+    },
+    ROTATION => {
+      TransformValue::Rotation(match kind {
+        Some(X) => Rotation::X(get_float(structure)?),
+        Some(Y) => Rotation::Y(get_float(structure)?),
+        Some(Z) => Rotation::Z(get_float(structure)?),
+        None | Some(AXIS) => {
+          let [angle, x, y, z] = get_array(structure)?;
+          Rotation::Axis(angle, Vector3::new(x, y, z))
+        },
+        Some(QUATERNION) => {
+          let [x, y, z, w] = get_array(structure)?;
+          Rotation::Quaternion(Quaternion::new(w, x, y, z))
+        },
+        Some(kind) => return Err(invalid_kind(structure, kind)),
+      })
+    },
+    SCALE => {
+      TransformValue::Scale(match kind {
+        Some(X) => Scale::X(get_float(structure)?),
+        Some(Y) => Scale::Y(get_float(structure)?),
+        Some(Z) => Scale::Z(get_float(structure)?),
+        None | Some(XYZ) => Scale::Xyz(Vector3::from(get_array::<3>(structure)?)),
+        Some(kind) => return Err(invalid_kind(structure, kind)),
+      })
+    },
+    _ => TransformValue::Transform(matrix_from_floats(&get_array(structure)?)),
+  };
+  
+  let mut transform = NodeTransform::new(value);
+  if let Some(Name::Local(ref name)) = structure.name {
+    transform.name = format!("%{}", name);
+  }
+  match structure.property(OBJECT) {
+    Some(&Literal::Bool(object)) => transform.object = object,
+    Some(_) => warnings.report(structure, format!("Expected a bool for the object of {}", structure.identifier))?,
+    None => {},
+  }
+  
+  Ok(transform)
 }
 
-impl Animation {
-  pub fn new() -> Animation {
-    Animation {
-      begin: 0.0,
-      end: 0.0,
-      tracks: Vec::new(),
+// The results of the registered handlers for the Extension structures in a
+// structure's substructures, or at the top level of the file. Extensions
+// without a handler are reported.
+fn read_extensions(structures: &[Structure], extensions: &ExtensionRegistry, warnings: &mut Warnings) -> Result<Vec<Extension>, ParseError> {
+  let mut results = Vec::new();
+  for child in structures.iter().filter(|child| child.identifier == EXTENSION) {
+    let applic = child.property(APPLIC).and_then(|applic| applic.as_str()).unwrap_or("");
+    let kind = child.property(TYPE).and_then(|kind| kind.as_str()).unwrap_or("");
+    match extensions.read(applic, kind, child)? {
+      Some(extension) => results.push(extension),
+      None => warnings.report(child, format!("No handler for Extension applic {} type {}", applic, kind))?,
     }
   }
+  Ok(results)
 }
 
-impl Track {
-  pub fn new() -> Track {
-    Track {
-      target: TargetType::Unknown,
-      time: Time::new(),
-      value: Value::new(),
+fn read_node<'a>(structure: &'a Structure, resolver: &Resolver<'a>, metric: &Metric, extensions: &ExtensionRegistry, warnings: &mut Warnings, parent_transform: &Matrix4<Scalar>) -> Result<Node, ParseError> {
+  let mut object_ref = "".to_string();
+  let mut material_refs = Vec::new();
+  let mut morph_weights = Vec::new();
+  let mut node = Node::new(NodeKind::Node);
+  if let Some(ref name) = structure.name {
+    node.node_ref = name.to_string();
+  }
+  
+  let known = [
+    NAME, OBJECT_REF, MATERIAL_REF, TRANSFORM, TRANSLATION, ROTATION, SCALE, MORPH_WEIGHT, ANIMATION, EXTENSION,
+    NODE, GEOMETRY_NODE, LIGHT_NODE, CAMERA_NODE, BONE_NODE,
+  ];
+  check_children(&structure.children, Some(structure), &known, warnings)?;
+  for child in &structure.children {
+    match child.identifier.as_str() {
+      NAME => {
+        node.name = get_string(child)?.to_string();
+      },
+      OBJECT_REF => {
+        let kind = match structure.identifier.as_str() {
+          LIGHT_NODE => LIGHT_OBJECT,
+          CAMERA_NODE => CAMERA_OBJECT,
+          _ => GEOMETRY_OBJECT,
+        };
+        if let Some(reference) = get_reference(child, resolver, kind)? {
+          object_ref = reference;
+        }
+      },
+      MATERIAL_REF => {
+        if let Some(material_ref) = get_reference(child, resolver, MATERIAL)? {
+          let index = get_index(child, INDEX, warnings)?.unwrap_or(0);
+          material_refs.push(MaterialRef { index, material_ref });
+        }
+      },
+      TRANSFORM | TRANSLATION | ROTATION | SCALE => {
+        node.transforms.push(read_transform(child, warnings)?);
+      },
+      MORPH_WEIGHT => {
+        let mut name = "".to_string();
+        if let Some(Name::Local(ref local)) = child.name {
+          name = format!("%{}", local);
+        }
+        let index = get_index(child, INDEX, warnings)?.unwrap_or(0);
+        morph_weights.push(MorphWeight { name, index, weight: get_float(child)? });
+      },
+      _ => {},
+    }
+  }
+  
+  // Tracks can target any transform or morph weight in the node
+  let transforms = structure.children.iter().filter(|child| matches!(child.identifier.as_str(), TRANSFORM | TRANSLATION | ROTATION | SCALE));
+  let mut targets: Vec<(&Structure, TargetType)> = transforms.zip(&node.transforms)
+    .map(|(child, transform)| (child, TargetType::from_transform(&transform.value)))
+    .collect();
+  let weights = structure.children.iter().filter(|child| child.identifier == MORPH_WEIGHT);
+  targets.extend(weights.zip(&morph_weights).map(|(child, weight)| (child, TargetType::MorphWeight(weight.index))));
+  for child in structure.children.iter().filter(|child| child.identifier == ANIMATION) {
+    node.animations.push(read_animation(child, resolver, &targets, metric, warnings)?);
+  }
+  
+  node.kind = match structure.identifier.as_str() {
+    GEOMETRY_NODE => NodeKind::Geometry(GeometryNode { object_ref, material_refs, morph_weights }),
+    LIGHT_NODE => NodeKind::Light(LightNode { object_ref }),
+    CAMERA_NODE => NodeKind::Camera(CameraNode { object_ref }),
+    BONE_NODE => NodeKind::Bone(BoneNode {}),
+    _ => NodeKind::Node,
+  };
+  node.extensions = read_extensions(&structure.children, extensions, warnings)?;
+  node.update_local_transform();
+  node.world_transform = parent_transform*node.local_transform;
+  
+  for child in &structure.children {
+    if is_node(child) {
+      let world_transform = node.world_transform;
+      node.children.push(read_node(child, resolver, metric, extensions, warnings, &world_transform)?);
     }
   }
+  
+  Ok(node)
+}
+
+fn is_node(structure: &Structure) -> bool {
+  matches!(structure.identifier.as_str(), NODE | GEOMETRY_NODE | LIGHT_NODE | CAMERA_NODE | BONE_NODE)
+}
+
+fn get_unsigned_ints(structure: &Structure) -> Result<Vec<u32>, ParseError> {
+  structure.primitive()
+    .and_then(|data| data.unsigned_integers())
+    .ok_or_else(|| type_mismatch(structure, "unsigned_int"))
 }
 
-impl Time {
-  pub fn new() -> Time {
-    Time {
-      curve: Curve::Unknown,
-      keys: Vec::with_capacity(3),
+// Bones have to be BoneNodes
+fn read_skeleton<'a>(structure: &'a Structure, resolver: &Resolver<'a>, warnings: &mut Warnings) -> Result<Skeleton, ParseError> {
+  let mut skeleton = Skeleton::new();
+  
+  check_children(&structure.children, Some(structure), &[BONE_REF_ARRAY, TRANSFORM], warnings)?;
+  for child in &structure.children {
+    match child.identifier.as_str() {
+      BONE_REF_ARRAY => {
+        let references = child.primitive()
+          .and_then(|data| data.references())
+          .ok_or_else(|| type_mismatch(child, "ref"))?;
+        for reference in references {
+          match resolve_reference(reference, child, resolver, BONE_NODE)? {
+            Some(bone_ref) => skeleton.bone_refs.push(bone_ref),
+            None => return Err(invalid_value(child, "Bones can't be null references".to_string())),
+          }
+        }
+      },
+      TRANSFORM => {
+        skeleton.bind_transforms = get_arrays(child)?.iter().map(matrix_from_floats).collect();
+      },
+      _ => {},
     }
   }
+  
+  if skeleton.bind_transforms.len() != skeleton.bone_refs.len() {
+    return Err(invalid_value(structure, format!("{} bones but {} bind transforms", skeleton.bone_refs.len(), skeleton.bind_transforms.len())));
+  }
+  
+  Ok(skeleton)
 }
 
-impl Value {
-  pub fn new() -> Value {
-    Value {
-      curve: Curve::Unknown,
-      keys: Vec::with_capacity(4),
+fn read_skin<'a>(structure: &'a Structure, resolver: &Resolver<'a>, vertex_count: usize, warnings: &mut Warnings) -> Result<Skin, ParseError> {
+  let mut skin = Skin::new();
+  let mut counts = Vec::new();
+  let mut indices = Vec::new();
+  let mut weights = Vec::new();
+  
+  let known = [TRANSFORM, SKELETON, BONE_COUNT_ARRAY, BONE_INDEX_ARRAY, BONE_WEIGHT_ARRAY];
+  check_children(&structure.children, Some(structure), &known, warnings)?;
+  for child in &structure.children {
+    match child.identifier.as_str() {
+      TRANSFORM => {
+        skin.bind_transform = matrix_from_floats(&get_array(child)?);
+      },
+      SKELETON => {
+        skin.skeleton = read_skeleton(child, resolver, warnings)?;
+      },
+      BONE_COUNT_ARRAY => {
+        counts = get_unsigned_ints(child)?;
+      },
+      BONE_INDEX_ARRAY => {
+        indices = get_unsigned_ints(child)?;
+      },
+      BONE_WEIGHT_ARRAY => {
+        weights = get_floats(child, 0, None)?.into_owned();
+      },
+      _ => {},
     }
   }
+  
+  if counts.len() != vertex_count {
+    return Err(invalid_value(structure, format!("{} bone counts for {} vertices", counts.len(), vertex_count)));
+  }
+  let total = counts.iter().map(|&count| count as usize).sum::<usize>();
+  if indices.len() != total || weights.len() != total {
+    return Err(invalid_value(structure, format!("{} bone indices and {} weights for {} influences", indices.len(), weights.len(), total)));
+  }
+  let bone_count = skin.skeleton.bone_refs.len() as u32;
+  if let Some(&bone) = indices.iter().find(|&&bone| bone >= bone_count) {
+    return Err(invalid_value(structure, format!("Bone index {} out of range for {} bones", bone, bone_count)));
+  }
+  
+  let mut start = 0;
+  for &count in &counts {
+    let end = start + count as usize;
+    skin.weights.push(indices[start..end].iter().zip(&weights[start..end])
+      .map(|(&bone, &weight)| BoneWeight { bone, weight })
+      .collect());
+    start = end;
+  }
+  
+  Ok(skin)
 }
 
-impl Key {
-  pub fn new() -> Key {
-    Key {
-      floats: Vec::new(),
-      key_type: KeyType::Single,
+fn read_geometry_object<'a>(structure: &'a Structure, resolver: &Resolver<'a>, extensions: &ExtensionRegistry, warnings: &mut Warnings) -> Result<GeometryObject, ParseError> {
+  let mut geometry_object = GeometryObject::new();
+  if let Some(ref name) = structure.name {
+    geometry_object.name = name.to_string();
+  }
+  
+  check_children(&structure.children, Some(structure), &[MESH, MORPH, EXTENSION], warnings)?;
+  geometry_object.extensions = read_extensions(&structure.children, extensions, warnings)?;
+  // Only the first level of detail is loaded
+  for mesh in structure.children_with(MESH).skip(1) {
+    warnings.report(mesh, "Only the first Mesh of a GeometryObject is loaded".to_string())?;
+  }
+  
+  if let Some(mesh) = structure.child(MESH) {
+    match mesh.property(PRIMITIVE).and_then(|primitive| primitive.as_str()) {
+      None | Some(TRIANGLES) => {},
+      Some(primitive) => warnings.report(mesh, format!("Mesh primitive {} is not supported", primitive))?,
+    }
+    check_children(&mesh.children, Some(mesh), &[VERTEXARRAY, INDEXARRAY, SKIN], warnings)?;
+    for index_array in mesh.children_with(INDEXARRAY).skip(1) {
+      warnings.report(index_array, "Only the first IndexArray of a Mesh is loaded".to_string())?;
+    }
+    
+    for child in mesh.children_with(VERTEXARRAY) {
+      let morph = get_index(child, MORPH_INDEX, warnings)?.unwrap_or(0);
+      match get_attrib(child) {
+        Some(POSITION) if morph != 0 => {
+          let vertex = get_arrays(child)?;
+          geometry_object.morph_vertex.push((morph, vertex));
+        },
+        Some(NORMAL) if morph != 0 => {
+          let normal = get_arrays(child)?;
+          geometry_object.morph_normal.push((morph, normal));
+        },
+        Some(POSITION) => {
+          geometry_object.vertex = get_arrays(child)?;
+        },
+        Some(NORMAL) => {
+          geometry_object.normal = get_arrays(child)?;
+        },
+        Some(TEXCOORD) if morph == 0 => {
+          geometry_object.texcoord = get_arrays(child)?;
+        },
+        Some(attrib) if morph != 0 => warnings.report(child, format!("Morph target VertexArray attrib {} is not supported", attrib))?,
+        Some(attrib) => warnings.report(child, format!("VertexArray attrib {} is not supported", attrib))?,
+        None => warnings.report(child, "VertexArray without an attrib".to_string())?,
+      }
+    }
+    
+    if let Some(index_array) = mesh.child(INDEXARRAY) {
+      geometry_object.index = index_array.primitive()
+        .filter(|data| data.array_size == 3)
+        .and_then(|data| data.unsigned_integers())
+        .ok_or_else(|| type_mismatch(index_array, "unsigned_int32[3]"))?;
+    }
+    
+    // The skin needs the vertex count, wherever the position array is
+    if let Some(skin) = mesh.child(SKIN) {
+      geometry_object.skin = Some(read_skin(skin, resolver, geometry_object.vertex.len(), warnings)?);
+    }
+  }
+  
+  for child in structure.children.iter().filter(|child| child.identifier == MORPH) {
+    check_children(&child.children, Some(child), &[NAME], warnings)?;
+    let index = get_index(child, INDEX, warnings)?.unwrap_or(0);
+    let mut morph = Morph::new(index);
+    morph.base = get_index(child, BASE, warnings)?;
+    if let Some(name) = child.child(NAME) {
+      morph.name = get_string(name)?.to_string();
+    }
+    geometry_object.morphs.push(morph);
+  }
+  
+  // Morph targets can have vertex arrays without a Morph structure
+  let indices: Vec<u32> = geometry_object.morph_vertex.iter().chain(&geometry_object.morph_normal).map(|&(index, _)| index).collect();
+  for index in indices {
+    if !geometry_object.morphs.iter().any(|morph| morph.index == index) {
+      geometry_object.morphs.push(Morph::new(index));
+    }
+  }
+  
+  let vertex_count = geometry_object.vertex.len();
+  for (index, vertex) in geometry_object.morph_vertex.iter().chain(&geometry_object.morph_normal) {
+    if vertex.len() != vertex_count {
+      return Err(invalid_value(structure, format!("Morph target {} has {} vertices but the mesh has {}", index, vertex.len(), vertex_count)));
     }
   }
+  for morph in &geometry_object.morphs {
+    if let Some(base) = morph.base {
+      if base != 0 && !geometry_object.morphs.iter().any(|other| other.index == base) {
+        return Err(invalid_value(structure, format!("Morph target {} is based on missing morph target {}", morph.index, base)));
+      }
+    }
+  }
+  
+  Ok(geometry_object)
 }
 
-struct InBasicNode {
-  num_brackets_open: i32,
-  in_use: bool,
+// The attribute of morph target index, falling back to the mesh's own
+fn morph_attrib<'a>(index: u32, base: &'a [[Scalar; 3]], morphs: &'a [(u32, Vec<[Scalar; 3]>)]) -> &'a [[Scalar; 3]] {
+  match morphs.iter().find(|&&(morph, _)| morph == index) {
+    Some((_, attrib)) => attrib,
+    None => base,
+  }
 }
 
-struct InIndexedNode {
-  num_brackets_open: i32,
-  in_use: bool,
-  position: usize,
+fn morph_deltas(target: &[[Scalar; 3]], base: &[[Scalar; 3]]) -> Vec<[Scalar; 3]> {
+  target.iter().zip(base).map(|(t, b)| [t[0] - b[0], t[1] - b[1], t[2] - b[2]]).collect()
 }
 
-struct InDoubleIndexedNode {
-  num_brackets_open: i32,
-  in_use: bool,
-  position: usize,
-  second_index: usize,
+// Deltas are taken after baking so they're in the same space as the model
+fn bake_morphs(object: &GeometryObject, transform: &Matrix4<Scalar>) -> Vec<Morph> {
+  let mut morphs = object.morphs.clone();
+  for morph in &mut morphs {
+    let base = morph.base.unwrap_or(0);
+    if object.morph_vertex.iter().any(|&(index, _)| index == morph.index) {
+      let target = bake_positions(morph_attrib(morph.index, &object.vertex, &object.morph_vertex), transform);
+      let base = bake_positions(morph_attrib(base, &object.vertex, &object.morph_vertex), transform);
+      morph.position_deltas = morph_deltas(&target, &base);
+    }
+    if object.morph_normal.iter().any(|&(index, _)| index == morph.index) {
+      let target = bake_normals(morph_attrib(morph.index, &object.normal, &object.morph_normal), transform);
+      let base = bake_normals(morph_attrib(base, &object.normal, &object.morph_normal), transform);
+      morph.normal_deltas = morph_deltas(&target, &base);
+    }
+  }
+  morphs
 }
 
-impl InBasicNode {
-  pub fn new() -> InBasicNode {
-    InBasicNode {
-      num_brackets_open: -1,
-      in_use: false,
-    }
+fn bake_positions(positions: &[[Scalar; 3]], transform: &Matrix4<Scalar>) -> Vec<[Scalar; 3]> {
+  let mut transformed_vertex: Vec<[Scalar; 3]> = Vec::with_capacity(positions.len());
+  for vertex in positions {
+    let temp_vtx = Vector4::new(vertex[0], vertex[1], vertex[2], 1.0);
+    let vtx = transform*temp_vtx;
+    transformed_vertex.push([vtx.x, vtx.y, vtx.z]);
   }
+  transformed_vertex
 }
 
-impl InIndexedNode {
-  pub fn new() -> InIndexedNode {
-    InIndexedNode {
-      num_brackets_open: -1,
-      in_use: false,
-      position: 0,
-    }
+// Normals stay perpendicular to the surface under non-uniform scales when
+// transformed by the inverse-transpose of the upper 3x3, and translation
+// doesn't apply to directions
+fn normal_matrix(transform: &Matrix4<Scalar>) -> Matrix3<Scalar> {
+  let upper = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate());
+  match upper.invert() {
+    Some(inverse) => inverse.transpose(),
+    None => upper,
   }
 }
 
-impl InDoubleIndexedNode {
-  pub fn new() -> InDoubleIndexedNode {
-    InDoubleIndexedNode {
-      num_brackets_open: -1,
-      in_use: false,
-      position: 0,
-      second_index: 0,
+fn bake_normals(normals: &[[Scalar; 3]], transform: &Matrix4<Scalar>) -> Vec<[Scalar; 3]> {
+  let normal_matrix = normal_matrix(transform);
+  let mut transformed_normal: Vec<[Scalar; 3]> = Vec::with_capacity(normals.len());
+  for normal in normals {
+    let mut nrml = normal_matrix*Vector3::new(normal[0], normal[1], normal[2]);
+    if nrml.magnitude2() > 0.0 {
+      nrml = nrml.normalize();
     }
+    transformed_normal.push([nrml.x, nrml.y, nrml.z]);
   }
+  transformed_normal
 }
 
-#[derive(Clone)]
-struct FinalModel {
-  vertices: VertexArray,
-  indices: IndexArray,
-  normals: NormalArray,
-  texcoords: TexCoordArray,
-  material_ref: String,
-  animation: Animation,
+fn read_material(structure: &Structure, extensions: &ExtensionRegistry, warnings: &mut Warnings) -> Result<Material, ParseError> {
+  let mut material = Material::new();
+  if let Some(ref name) = structure.name {
+    material.material_ref = name.to_string();
+  }
+  
+  check_children(&structure.children, Some(structure), &[COLOUR, TEXTURE, EXTENSION], warnings)?;
+  material.extensions = read_extensions(&structure.children, extensions, warnings)?;
+  for child in &structure.children {
+    match child.identifier.as_str() {
+      COLOUR => {
+        match get_attrib(child) {
+          Some(DIFFUSE) => {
+            // Colours are either rgb or rgba
+            material.diffuse_colour = match child.primitive().map(|data| data.array_size) {
+              Some(4) => {
+                let [r, g, b, _] = get_array::<4>(child)?;
+                [r, g, b]
+              },
+              _ => get_array::<3>(child)?,
+            };
+          },
+          Some(attrib) => warnings.report(child, format!("Color attrib {} is not supported", attrib))?,
+          None => warnings.report(child, "Color without an attrib".to_string())?,
+        }
+      },
+      TEXTURE => {
+        check_children(&child.children, Some(child), &[], warnings)?;
+        let mut texture = Texture::new();
+        texture.attrib = match get_attrib(child) {
+          Some(DIFFUSE) => Attrib::Diffuse,
+          Some(SPECULAR) => Attrib::Specular,
+          Some(NORMAL) => Attrib::Normal,
+          Some(attrib) => {
+            warnings.report(child, format!("Texture attrib {} is not supported", attrib))?;
+            Attrib::Unknown
+          },
+          None => {
+            warnings.report(child, "Texture without an attrib".to_string())?;
+            Attrib::Unknown
+          },
+        };
+        // Blender marks paths relative to the exported file with a leading //
+        texture.texture = get_string(child)?.trim_start_matches("//").to_string();
+        material.textures.push(texture);
+      },
+      _ => {},
+    }
+  }
+  
+  Ok(material)
 }
 
 pub struct OpengexPaser {
-  metric: Metric,
+  scene: Scene,
   models: Vec<FinalModel>,
+  meshes: Vec<Mesh>,
+  instances: Vec<Instance>,
   materials: Vec<Material>,
+  // Extensions at the top level of the file. The others are kept with the
+  // node, mesh or material they're in.
+  extensions: Vec<Extension>,
+  metric: Metric,
+  warnings: Vec<Warning>,
 }
 
 impl OpengexPaser {
+  #[deprecated(note = "use OpengexPaser::load, which reports why a file failed to load")]
   pub fn new(location: String) -> OpengexPaser {
+    // Files that fail to load give an empty model
+    OpengexPaser::load(&location).unwrap_or_else(|_| {
+      OpengexPaser {
+        scene: Scene::new(),
+        models: Vec::new(),
+        meshes: Vec::new(),
+        instances: Vec::new(),
+        materials: Vec::new(),
+        extensions: Vec::new(),
+        metric: Metric::new(),
+        warnings: Vec::new(),
+      }
+    })
+  }
+  
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<OpengexPaser, ParseError> {
+    OpengexPaser::load_with_options(path, &ParseOptions::new())
+  }
+  
+  pub fn load_with_options<P: AsRef<Path>>(path: P, options: &ParseOptions) -> Result<OpengexPaser, ParseError> {
+    let path = path.as_ref();
+    
+    let mut model = File::open(path)
+      .map_err(|error| ParseError::Io { location: Location::new(0, 0), error })
+      .and_then(|file| OpengexPaser::from_reader_with_options(file, options))
+      .map_err(|e| e.with_path(path.to_path_buf()))?;
+    for warning in &mut model.warnings {
+      warning.location.path = Some(path.to_path_buf());
+    }
+    Ok(model)
+  }
+  
+  pub fn from_reader<R: Read>(reader: R) -> Result<OpengexPaser, ParseError> {
+    OpengexPaser::from_reader_with_options(reader, &ParseOptions::new())
+  }
+  
+  pub fn from_reader_with_options<R: Read>(mut reader: R, options: &ParseOptions) -> Result<OpengexPaser, ParseError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(|error| ParseError::Io { location: Location::new(0, 0), error })?;
+    
+    OpengexPaser::from_bytes_with_options(&bytes, options)
+  }
+  
+  pub fn from_bytes(bytes: &[u8]) -> Result<OpengexPaser, ParseError> {
+    OpengexPaser::from_bytes_with_options(bytes, &ParseOptions::new())
+  }
+  
+  pub fn from_bytes_with_options(bytes: &[u8], options: &ParseOptions) -> Result<OpengexPaser, ParseError> {
+    let source = match str::from_utf8(bytes) {
+      Ok(source) => source,
+      Err(e) => {
+        let valid = &bytes[..e.valid_up_to()];
+        let line = valid.iter().filter(|&&b| b == b'\n').count() + 1;
+        let column = valid.len() - valid.iter().rposition(|&b| b == b'\n').map(|i| i + 1).unwrap_or(0) + 1;
+        return Err(ParseError::Syntax {
+          location: Location::new(line, column),
+          message: "Invalid UTF-8".to_string(),
+        });
+      }
+    };
+    
+    OpengexPaser::from_str_with_options(source, options)
+  }
+  
+  pub fn from_str_with_options(source: &str, options: &ParseOptions) -> Result<OpengexPaser, ParseError> {
+    let document: Document = source.parse()?;
+    OpengexPaser::from_document_with_options(&document, options)
+  }
+  
+  // Loads the model from an already parsed document, so structures the
+  // crate doesn't know about can be read from the same document
+  pub fn from_document(document: &Document) -> Result<OpengexPaser, ParseError> {
+    OpengexPaser::from_document_with_options(document, &ParseOptions::new())
+  }
+  
+  // Extension structures are passed to the handler registered for their
+  // applic and type, and the results kept with the structure they're in
+  pub fn from_document_with_extensions(document: &Document, registry: &ExtensionRegistry) -> Result<OpengexPaser, ParseError> {
+    let options = ParseOptions {
+      extensions: registry.clone(),
+      ..ParseOptions::new()
+    };
+    OpengexPaser::from_document_with_options(document, &options)
+  }
+  
+  pub fn from_document_with_options(document: &Document, options: &ParseOptions) -> Result<OpengexPaser, ParseError> {
+    let structures = &document.structures;
+    let registry = &options.extensions;
+    let mut warnings = Warnings::new(options.strict);
     let mut metric = Metric::new();
     
-    let mut num_nodes: i32 = 0;
-    let mut geometry_nodes: Vec<GeometryNode> = Vec::new();
+    let mut scene = Scene::new();
     let mut geometry_objects: Vec<GeometryObject> = Vec::new();
     let mut materials: Vec<Material> = Vec::new();
     
-    let mut in_geometrynode = InIndexedNode::new();
-    let mut in_transform = InBasicNode::new();
-    let mut in_float2 = InBasicNode::new();
-    let mut in_float3 = InBasicNode::new();
-    let mut in_float16 = InIndexedNode::new();
-    let mut in_unsigned_int3 = InBasicNode::new();
-    
-    let mut in_geometryobject = InIndexedNode::new();
-    let mut in_vertexposition = InBasicNode::new();
-    let mut in_vertexnormal = InBasicNode::new();
-    let mut in_texcoord = InBasicNode::new();
-    let mut in_index = InBasicNode::new();
-    let mut in_material = InDoubleIndexedNode::new();
-    let mut in_texture = InBasicNode::new();
-    
-    let mut in_animation = InIndexedNode::new();
-    let mut in_track = InIndexedNode::new();
-    let mut in_time = InIndexedNode::new();
-    let mut in_value = InIndexedNode::new();
-    
-    let mut num_brackets_open = 0;
-    
-    if let Ok(file) = File::open(location.clone()) {
-      let file = BufReader::new(file);
+    // Every reference has to resolve, wherever it is in the file
+    let resolver = Resolver::new(document)?;
+    resolver.check()?;
+    
+    let known = [METRIC, NODE, GEOMETRY_NODE, LIGHT_NODE, CAMERA_NODE, BONE_NODE, GEOMETRY_OBJECT, MATERIAL, EXTENSION];
+    check_children(structures, None, &known, &mut warnings)?;
+    
+    for structure in structures.iter().filter(|structure| structure.identifier == METRIC) {
+      read_metric(structure, &mut metric, &mut warnings)?;
+    }
+    
+    if let Some(ref target) = options.conversion {
+      if Conversion::new(Axis::Y, Axis::NegativeZ, target).is_none() {
+        return Err(ParseError::InvalidValue {
+          location: Location::new(0, 0),
+          message: "The conversion's up and forward directions are along the same axis".to_string(),
+        });
+      }
+    }
+    // Without a conversion the model stays in the file's own axes
+    let target = |metric: &Metric| options.conversion.unwrap_or(ConversionOptions {
+      up: metric.up,
+      forward: metric.forward(),
+      handedness: Handedness::Right,
+    });
+    let conversion = match Conversion::new(metric.up, metric.forward(), &target(&metric)) {
+      Some(conversion) => conversion,
+      None => {
+        let forward = structures.iter().rev()
+          .find(|structure| structure.identifier == METRIC && structure.property(KEY).and_then(|key| key.as_str()) == Some(FORWARD));
+        if let Some(forward) = forward {
+          warnings.report(forward, "Metric forward is along the up direction".to_string())?;
+        }
+        metric.forward = None;
+        Conversion::new(metric.up, metric.forward(), &target(&metric)).expect("the default forward direction is never along up")
+      },
+    };
+    let conversion = conversion.with_units(&metric, options.units.as_ref());
+    
+    // Skins refer to bones, so the scene is read before the objects
+    for structure in structures.iter().filter(|structure| is_node(structure)) {
+      scene.nodes.push(read_node(structure, &resolver, &metric, registry, &mut warnings, &Matrix4::identity())?);
+    }
+    for node in &mut scene.nodes {
+      conversion.node(node);
+      node.update_world_transform(&Matrix4::identity());
+    }
+    
+    for structure in structures {
+      match structure.identifier.as_str() {
+        GEOMETRY_OBJECT => {
+          let mut geometry_object = read_geometry_object(structure, &resolver, registry, &mut warnings)?;
+          conversion.geometry_object(&mut geometry_object);
+          geometry_objects.push(geometry_object);
+        },
+        MATERIAL => {
+          materials.push(read_material(structure, registry, &mut warnings)?);
+        },
+        _ => {},
+      }
+    }
+    
+    let meshes: Vec<Mesh> = geometry_objects.iter().map(|object| Mesh {
+      object_ref: object.name.clone(),
+      positions: object.vertex.clone(),
+      normals: object.normal.clone(),
+      texcoords: object.texcoord.clone(),
+      indices: object.index.clone(),
+      skin: object.skin.clone(),
+      morphs: bake_morphs(object, &Matrix4::identity()),
+      extensions: object.extensions.clone(),
+    }).collect();
+    let mut instances = Vec::new();
+    let mut models: Vec<FinalModel> = Vec::with_capacity(geometry_objects.len());
+    
+    for node in scene.iter() {
+      // A GeometryNode without an ObjectRef draws nothing, even when there
+      // are GeometryObjects without a name
+      let geometry = match node.geometry() {
+        Some(geometry) if !geometry.object_ref.is_empty() => geometry,
+        _ => continue,
+      };
+      let transform = node.world_transform*node.object_transform;
       
-      for line in file.lines() {
-        let line = line.expect("Unable to read line");
-        let line = line.trim();
-        let line = line.trim_left();
-        let line = line.trim_matches('\t');
-        let mut v: Vec<&str> = line.split(" ").collect();
+      if let Some(mesh) = geometry_objects.iter().position(|object| object.name == geometry.object_ref) {
+        instances.push(Instance {
+          node_ref: node.node_ref.clone(),
+          mesh,
+          local_transform: node.local_transform,
+          world_transform: node.world_transform,
+          transform,
+          material_refs: geometry.material_refs.clone(),
+          morph_weights: geometry.morph_weights.clone(),
+        });
         
-        if v[0].contains(FLOAT2) || v[0].contains(FLOAT3) ||  v[0].contains(UNSIGNED_INT3) {
-          v[0] = remove_brackets(v[0]);
+        let object = &geometry_objects[mesh];
+        let (transformed_vertex, transformed_normal, morphs) = if options.bake {
+          (bake_positions(&object.vertex, &transform), bake_normals(&object.normal, &transform), bake_morphs(object, &transform))
+        } else {
+          (object.vertex.clone(), object.normal.clone(), meshes[mesh].morphs.clone())
+        };
+        
+        let mut material_ref = "".to_string();
+        if let Some(materialref) = geometry.material_refs.iter().find(|materialref| materialref.index == 0) {
+          material_ref = materialref.material_ref.clone();
         }
         
-        match v[0] {
-          METRIC => {
-            if v[1] == KEY && v[2] == EQUALS {
-              match v[3] {
-                DISTANCE => {
-                  if v[4] == FLOAT {
-                    if let Some(float) = get_float(vec!(v[5])) {
-                      metric.distance = float;
-                    }
-                  }
-                },
-                ANGLE => {
-                  if v[4] == FLOAT {
-                    if let Some(float) = get_float(vec!(v[5])) {
-                      metric.angle= float;
-                    }
-                  }
-                },
-                TIME => {
-                  if v[4] == FLOAT {
-                    if let Some(float) = get_float(vec!(v[5])) {
-                      metric.time = float;
-                    }
-                  }
-                },
-                UP => {
-                   if v[4] == STRING {
-                     if let Some(dir) = get_string_value(vec!(v[5])) {
-                       metric.up = dir.to_string();
-                     }
-                   }
-                },
-                FORWARD => {
-                  if v[4] == STRING {
-                    if let Some(forward) = get_string_value(vec!(v[5])) {
-                      metric.forward = forward.to_string();
-                    } 
-                  }
-                },
-                _ => {
-                  
-                }
-              }
-            }
-          },
-          GEOMETRY_NODE => {
-            in_geometrynode.num_brackets_open = num_brackets_open;
-            in_geometrynode.in_use = true;
-            in_geometrynode.position = num_nodes as usize;
-            
-            num_nodes += 1;
-            
-            geometry_nodes.push(GeometryNode::new(v[1].to_string()));
-          },
-          NAME => {
-            if v[1] == STRING {
-              let name = remove_brackets(v[2]);
-              if in_geometrynode.in_use {
-                geometry_nodes[in_geometrynode.position].name = name.to_string();
-              }
-              if in_material.in_use {
-                materials[in_material.position].name = name.to_string();
-              }
-            }
-          },
-          OBJECT_REF => {
-            if v[1] == REF {
-              let objectref = remove_brackets(v[2]);
-              if in_geometrynode.in_use {
-                geometry_nodes[in_geometrynode.position].object_ref = objectref.to_string();
-              }
-            }
-          },
-          MATERIAL_REF => {
-            if v[1] == INDEX {
-              if v[2] == EQUALS {
-                // material index
-                let index_str = remove_brackets(v[3]);
-                let mut index = 0;
-                if let Ok(int) = index_str.parse::<i32>() {
-                   index = int;
-                }
-                
-                if index == 0 { // Material with index = 0 is default
-                  if v[4] == REF {
-                    if in_geometrynode.in_use {
-                      let materialref = remove_brackets(v[5]);
-                      let material_index = geometry_nodes[in_geometrynode.position].materialref.len();
-                      geometry_nodes[in_geometrynode.position].materialref.push(MaterialRef::new());
-                      geometry_nodes[in_geometrynode.position].materialref[material_index].index = index as i32;
-                      geometry_nodes[in_geometrynode.position].materialref[material_index].material_ref = materialref.to_string();
-                    }
-                  }
-                }
-              }
-            }
-          },
-          TRANSFORM => {
-            in_transform.num_brackets_open = num_brackets_open;
-            in_transform.in_use = true;
-          },
-          FLOAT2 => {
-            in_float2.num_brackets_open = num_brackets_open;
-            in_float2.in_use = true;
-          },
-          FLOAT3 => {
-            in_float3.num_brackets_open = num_brackets_open;
-            in_float3.in_use = true;
-          },
-          FLOAT16 => {
-            in_float16.num_brackets_open = num_brackets_open;
-            in_float16.in_use = true;
-            in_float16.position = 0;
-          },
-          UNSIGNED_INT3 => {
-            in_unsigned_int3.num_brackets_open = num_brackets_open;
-            in_unsigned_int3.in_use = true;
-          },
-          GEOMETRY_OBJECT => {
-            let name = remove_brackets(v[1]);
-            
-            in_geometryobject.num_brackets_open = num_brackets_open;
-            in_geometryobject.in_use = true;
-            
-            let index = geometry_objects.len();
-            in_geometryobject.position = index;
-            
-            geometry_objects.push(GeometryObject::new());
-            geometry_objects[index].name = name.to_string();
-          },
-          MESH => {
-            if in_geometryobject.in_use {
-              if v[1] == PRIMITIVE {
-                if v[2] == EQUALS {
-                  let mesh_name = remove_brackets(v[3]);
-                  geometry_objects[in_geometryobject.position].mesh = mesh_name.to_string();
-                }
-              }
-            }
-          },
-          VERTEXARRAY => {
-            if in_geometryobject.in_use {
-              if v[1] == ATTRIB {
-                if v[2] == EQUALS {
-                  if v[3] == POSITION {
-                    in_vertexposition.num_brackets_open = num_brackets_open;
-                    in_vertexposition.in_use = true;
-                  } else
-                  if v[3] == NORMAL {
-                    in_vertexnormal.num_brackets_open = num_brackets_open;
-                    in_vertexnormal.in_use = true;
-                  } else
-                  if v[3] == TEXCOORD {
-                    in_texcoord.num_brackets_open = num_brackets_open;
-                    in_texcoord.in_use = true;
-                  }
-                }
-              }
-            }
-          },
-          INDEXARRAY => {
-            if in_geometryobject.in_use {
-              in_index.num_brackets_open = num_brackets_open;
-              in_index.in_use = true;
-            }
-          },
-          MATERIAL => {
-            in_material.num_brackets_open = num_brackets_open;
-            in_material.in_use = true;
-            in_material.second_index = 0;
-            let materialref = remove_brackets(v[1]);
-            let index = materials.len();
-            
-            materials.push(Material::new());
-            materials[index].material_ref = materialref.to_string();
-            
-            in_material.position = index;
-          }
-          TEXTURE => {
-            if in_material.in_use {
-              if v[1]  == ATTRIB {
-                if v[2] == EQUALS {
-                  in_texture.num_brackets_open = num_brackets_open;
-                  in_texture.in_use = true;
-                  
-                  in_material.second_index = materials[in_material.position].textures.len();
-                  
-                  materials[in_material.position].textures.push(Texture::new());
-                  if v[3] == DIFFUSE {
-                    materials[in_material.position].textures[in_material.second_index].attrib = Attrib::Diffuse;
-                  }
-                  if v[3] == SPECULAR {
-                    materials[in_material.position].textures[in_material.second_index].attrib = Attrib::Specular;
-                  }
-                  if v[3] == NORMAL {
-                    materials[in_material.position].textures[in_material.second_index].attrib = Attrib::Normal;
-                  }
-                }
-              }
-            }
-          },
-          PLAINSTRING => {
-            if in_material.in_use && in_texture.in_use {
-              let texture = remove_brackets(v[1]);
-              materials[in_material.position].textures[in_material.second_index].texture = texture.to_string();
-            }
-          },
-          COLOUR => {
-            if in_material.in_use {
-              if v[1] == ATTRIB {
-                if v[2] == EQUALS {
-                  if v[3] == DIFFUSE {
-                    let float_type = remove_brackets(v[4]);
-                    if float_type == FLOAT3 {
-                      let mut colour: [f32; 3] = [0.0,0.0,0.0];
-                      let mut idx = 0;
-                      for i in 0..(v.len()-5) {
-                        let value = remove_brackets(v[5 + i]);
-                        if let Ok(float) = value.parse::<f32>() {
-                          colour[idx] = float;
-                          idx += 1;
-                          if idx == 3 {
-                            let temp_colour = colour;
-                            materials[in_material.position].diffuse_colour = temp_colour;
-                            idx = 0;
-                            colour = [0.0, 0.0, 0.0];
-                          }
-                        }
-                      }
-                    }
-                  }
-                }
-              }
-            }
-          },
-          ANIMATION => {
-            if in_geometrynode.in_use {
-              in_animation.num_brackets_open = num_brackets_open;
-              in_animation.in_use = true;
-              geometry_nodes[in_geometrynode.position].animation = Some(Animation::new());
-              if let Some(ref mut animation) = geometry_nodes[in_geometrynode.position].animation {
-                if v.len() > 1 {
-                  if v[1] == BEGIN {
-                    if v[2] == EQUALS {
-                      let begin_time = get_raw_float(remove_brackets(v[3]));
-                      if let Some(time) = begin_time {
-                        animation.begin = time;
-                      }
-                      if v[3] == END {
-                        if v[4] == EQUALS {
-                          let end_time = get_raw_float(remove_brackets(v[5]));
-                          if let Some(time) = end_time {
-                            animation.end = time;
-                          }
-                        }
-                      }
-                    }
-                  }
-                }
-              }
-            }
-          },
-          TRACK => {
-            if in_geometrynode.in_use && in_animation.in_use {
-              in_track.num_brackets_open = num_brackets_open;
-              in_track.in_use = true;
-              in_track.position = in_animation.position;
-              if let Some(ref mut animation) = geometry_nodes[in_geometrynode.position].animation {
-                animation.tracks.push(Track::new());
-                let mut target_type = TargetType::Unknown;
-                if v[1] == TARGET {
-                  if v[2] == EQUALS {
-                    match v[3] {
-                      XPOS => {
-                        target_type = TargetType::Xpos;
-                      },
-                      YPOS => {
-                        target_type = TargetType::Ypos;
-                      },
-                      ZPOS => {
-                        target_type = TargetType::Zpos;
-                      },
-                      _ => {}
-                    }
-                    animation.tracks[in_track.position].target = target_type;
-                  }
-                }
-              }
-            }
-          },
-          TRACK_TIME => {
-            if in_geometrynode.in_use && in_animation.in_use && in_track.in_use {
-              in_time.num_brackets_open = num_brackets_open;
-              in_time.in_use = true;
-              if let Some(ref mut animation) = geometry_nodes[in_geometrynode.position].animation {
-                if v[1] == CURVE {
-                  if v[2] == EQUALS {
-                    let mut curve_type = Curve::Unknown;
-                    if v[3] == LINEAR {
-                      curve_type = Curve::Linear;
-                    }
-                    if v[3] == BEZIER {
-                      curve_type = Curve::Bezier;
-                    }
-                    animation.tracks[in_track.position].time = Time::new();
-                    animation.tracks[in_track.position].time.curve = curve_type;
-                  }
-                }
-              }
-            }
-          },
-          VALUE => {
-            if in_geometrynode.in_use && in_animation.in_use && in_track.in_use {
-              in_value.num_brackets_open = num_brackets_open;
-              in_value.in_use = true;
-              if let Some(ref mut animation) = geometry_nodes[in_geometrynode.position].animation {
-                if v[1] == CURVE {
-                  if v[2] == EQUALS {
-                    let mut curve_type = Curve::Unknown;
-                    if v[3] == LINEAR {
-                      curve_type = Curve::Linear;
-                    }
-                    if v[3] == BEZIER {
-                      curve_type = Curve::Bezier;
-                    }
-                    animation.tracks[in_track.position].value = Value::new();
-                    animation.tracks[in_track.position].value.curve = curve_type;
-                  }
-                }
-              }
-            }
-          },
-          TRACK_KEY => {
-            if in_geometrynode.in_use && in_animation.in_use && in_track.in_use {
-              if let Some(ref mut animation) = geometry_nodes[in_geometrynode.position].animation {
-                if in_time.in_use { 
-                  let mut key_type = KeyType::Single;
-                  let mut offset = 2;
-                  match v[1] {
-                    FLOAT => {
-                      key_type = KeyType::Single;
-                    },
-                    KIND => {
-                      offset = 4;
-                      if v[2] == EQUALS {
-                        match v[3] {
-                          PLUSCONTROL => {
-                            key_type = KeyType::PlusControl;
-                          },
-                          MINUSCONTROL => {
-                            key_type = KeyType::MinusControl;
-                          },
-                          _ => {}
-                        }
-                      }
-                    },
-                    _ => {}
-                  }
-                  animation.tracks[in_track.position].time.keys.push(Key::new());
-                  animation.tracks[in_track.position].time.keys[in_time.position].key_type = key_type;
-                  for i in offset..v.len() {
-                    let value = get_raw_float(remove_brackets(v[i]));
-                    if let Some(value) = value {
-                      animation.tracks[in_track.position].time.keys[in_time.position].floats.push(value);
-                    }
-                  }
-                  in_time.position += 1;
-                }
-                if in_value.in_use { 
-                  let mut key_type = KeyType::Single;
-                  let mut offset = 2;
-                  match v[1] {
-                    FLOAT => {
-                      key_type = KeyType::Single;
-                    },
-                    KIND => {
-                      offset = 4;
-                      if v[2] == EQUALS {
-                        match v[3] {
-                          PLUSCONTROL => {
-                            key_type = KeyType::PlusControl;
-                          },
-                          MINUSCONTROL => {
-                            key_type = KeyType::MinusControl;
-                          },
-                          _ => {}
-                        }
-                      }
-                    },
-                    _ => {}
-                  }
-                  animation.tracks[in_track.position].value.keys.push(Key::new());
-                  animation.tracks[in_track.position].value.keys[in_value.position].key_type = key_type;
-                  for i in offset..v.len() {
-                    let value = get_raw_float(remove_brackets(v[i]));
-                    if let Some(value) = value {
-                      animation.tracks[in_track.position].value.keys[in_value.position].floats.push(value);
-                    }
-                  }
-                  in_value.position += 1;
-                }
-              }
-            }
-          },
-          OPEN_BRACKET => {
-            num_brackets_open += 1;
-          },
-          CLOSE_BRACKET => {
-            num_brackets_open -= 1;
-            if in_geometrynode.in_use {
-              if in_geometrynode.num_brackets_open == num_brackets_open {
-                in_geometrynode.num_brackets_open = -1;
-                in_geometrynode.in_use = false;
-                in_animation.position = 0;
-              }
-            }
-            if in_transform.in_use {
-              if in_transform.num_brackets_open == num_brackets_open {
-                in_transform.num_brackets_open = -1;
-                in_transform.in_use = false;
-              }
-            }
-            if in_float2.in_use {
-              if in_float2.num_brackets_open == num_brackets_open {
-                in_float2.num_brackets_open = -1;
-                in_float2.in_use = false;
-              }
-            }
-            if in_float3.in_use {
-              if in_float3.num_brackets_open == num_brackets_open {
-                in_float3.num_brackets_open = -1;
-                in_float3.in_use = false;
-              }
-            }
-            if in_float16.in_use {
-              if in_float16.num_brackets_open == num_brackets_open {
-                in_float16.num_brackets_open = -1;
-                in_float16.in_use = false;
-                in_float16.position = 0;
-              }
-            }
-            if in_unsigned_int3.in_use {
-              if in_unsigned_int3.num_brackets_open == num_brackets_open {
-                in_unsigned_int3.num_brackets_open = -1;
-                in_unsigned_int3.in_use = false;
-              }
-            }
-            if in_geometryobject.in_use {
-              if in_geometryobject.num_brackets_open == num_brackets_open {
-                in_geometryobject.num_brackets_open = -1;
-                in_geometryobject.in_use = false;
-                in_geometryobject.position = 0;
-              }
-            }
-            if in_vertexposition.in_use {
-              if in_vertexposition.num_brackets_open == num_brackets_open {
-                in_vertexposition.num_brackets_open = -1;
-                in_vertexposition.in_use = false;
-              }
-            }
-            if in_vertexnormal.in_use {
-              if in_vertexnormal.num_brackets_open == num_brackets_open {
-                in_vertexnormal.num_brackets_open = -1;
-                in_vertexnormal.in_use = false;
-              }
-            }
-            if in_texcoord.in_use {
-              if in_texcoord.num_brackets_open == num_brackets_open {
-                in_texcoord.num_brackets_open = -1;
-                in_texcoord.in_use = false;
-              }
-            }
-            if in_index.in_use {
-              if in_index.num_brackets_open == num_brackets_open {
-                in_index.num_brackets_open = -1;
-                in_index.in_use = false;
-              }
-            }
-            if in_material.in_use {
-              if in_material.num_brackets_open == num_brackets_open {
-                in_material.num_brackets_open = -1;
-                in_material.in_use = false;
-                in_material.position = 0;
-                in_material.second_index = 0;
-              }
-            }
-            if in_texture.in_use {
-              if in_texture.num_brackets_open == num_brackets_open {
-                in_texture.num_brackets_open = -1;
-                in_texture.in_use = false;
-              }
-            }
-            if in_animation.in_use {
-              if in_animation.num_brackets_open == num_brackets_open {
-                in_animation.num_brackets_open = -1;
-                in_animation.in_use = false;
-                in_animation.position += 1;
-              }
-            }
-            if in_track.in_use {
-              if in_track.num_brackets_open == num_brackets_open {
-                in_track.num_brackets_open = -1;
-                in_track.in_use = false;
-                in_track.position = 0;
-              }
-            }
-            if in_time.in_use {
-              if in_time.num_brackets_open == num_brackets_open {
-                in_time.num_brackets_open = -1;
-                in_time.in_use = false;
-                in_time.position = 0;
-              }
-            }
-            if in_value.in_use {
-              if in_value.num_brackets_open == num_brackets_open {
-                in_value.num_brackets_open = -1;
-                in_value.in_use = false;
-                in_value.position = 0;
-              }
-            }
-          },
-          _ => {
-            if v[0].len() > 1 && v[0].contains(char::is_numeric) {
-              if in_geometrynode.in_use {
-                if in_transform.in_use {
-                  if in_float16.in_use {
-                    for i in 0..v.len() {
-                      let value = remove_brackets(v[i]);
-                      if let Ok(float) = value.parse::<f32>() {
-                        geometry_nodes[in_geometrynode.position].raw_transform[in_float16.position] = float;
-                        in_float16.position += 1;
-                      }
-                    }
-                  }
-                }
-              }
-              if in_geometryobject.in_use {
-                if in_vertexposition.in_use {
-                  if in_float3.in_use {
-                    let mut vtx: [f32; 3] = [0.0,0.0,0.0];
-                    let mut idx = 0;
-                    for i in 0..v.len() {
-                      let value = remove_brackets(v[i]);
-                      if let Ok(float) = value.parse::<f32>() {
-                        vtx[idx] = float;
-                        idx += 1;
-                        if idx == 3 {
-                          let temp_vtx = vtx;
-                          geometry_objects[in_geometryobject.position].vertex.push(temp_vtx);
-                          idx = 0;
-                          vtx = [0.0, 0.0, 0.0];
-                        }
-                      }
-                    }
-                  }
-                }
-                if in_vertexnormal.in_use {
-                  if in_float3.in_use {
-                    let mut nrml: [f32; 3] = [0.0,0.0,0.0];
-                    let mut idx = 0;
-                    for i in 0..v.len() {
-                      let value = remove_brackets(v[i]);
-                      if let Ok(float) = value.parse::<f32>() {
-                        nrml[idx] = float;
-                        idx += 1;
-                        if idx == 3 {
-                          let temp_nrml = nrml;
-                          geometry_objects[in_geometryobject.position].normal.push(temp_nrml);
-                          idx = 0;
-                          nrml = [0.0, 0.0, 0.0];
-                        }
-                      }
-                    }
-                  }
-                }
-                if in_texcoord.in_use {
-                  if in_float2.in_use {
-                    let mut texcoord: [f32; 2] = [0.0,0.0];
-                    let mut idx = 0;
-                    for i in 0..v.len() {
-                      let value = remove_brackets(v[i]);
-                      if let Ok(float) = value.parse::<f32>() {
-                        texcoord[idx] = float;
-                        idx += 1;
-                        if idx == 2 {
-                          let temp_texcoord = texcoord;
-                          geometry_objects[in_geometryobject.position].texcoord.push(temp_texcoord);
-                          idx = 0;
-                          texcoord = [0.0, 0.0];
-                        }
-                      }
-                    }
-                  }
-                }
-                if in_index.in_use {
-                  if in_unsigned_int3.in_use {
-                    for i in 0..v.len() {
-                      let value = remove_brackets(v[i]);
-                      if let Ok(unsigned) = value.parse::<u32>() {
-                        geometry_objects[in_geometryobject.position].index.push(unsigned);
-                      }
-                    }
-                  }
-                }
-                if in_texture.in_use {
-                  if in_transform.in_use {
-                    if in_float16.in_use {
-                      let mut vtx: [f32; 3] = [0.0,0.0,0.0];
-                      let mut idx = 0;
-                      for i in 0..v.len() {
-                        let value = remove_brackets(v[i]);
-                        if let Ok(float) = value.parse::<f32>() {
-                          materials[in_material.position].textures[in_material.second_index].raw_transform[in_float16.position] = float;
-                          in_float16.position += 1;
-                        }
-                      }
-                    }
-                  }
-                }
-              }
-            }
-          }
-        }
+        models.push(FinalModel {
+          vertices: transformed_vertex,
+          indices: object.index.clone(),
+          normals: transformed_normal,
+          texcoords: object.texcoord.clone(),
+          skin: object.skin.clone(),
+          morphs,
+          morph_weights: geometry.morph_weights.clone(),
+          material_ref,
+        });
       }
-    } else {
-      println!("Error: Model file at location {:?} does not exist!", location);
     }
     
-    let mut models: Vec<FinalModel> = Vec::with_capacity(geometry_objects.len());
+    let extensions = read_extensions(structures, registry, &mut warnings)?;
     
-    let mut model_index = 0;
-    
-    for i in 0..geometry_nodes.len() {
-      let transform = Matrix4::new(
-        geometry_nodes[i].raw_transform[0], geometry_nodes[i].raw_transform[1], geometry_nodes[i].raw_transform[2], geometry_nodes[i].raw_transform[3], 
-        geometry_nodes[i].raw_transform[4], geometry_nodes[i].raw_transform[5], geometry_nodes[i].raw_transform[6], geometry_nodes[i].raw_transform[7], 
-        geometry_nodes[i].raw_transform[8], geometry_nodes[i].raw_transform[9], geometry_nodes[i].raw_transform[10], geometry_nodes[i].raw_transform[11], 
-        geometry_nodes[i].raw_transform[12], geometry_nodes[i].raw_transform[13], geometry_nodes[i].raw_transform[14], geometry_nodes[i].raw_transform[15], 
-      );
-      
-      for j in 0..geometry_objects.len() {
-        if geometry_nodes[i].object_ref == geometry_objects[j].name {
-          models.push(FinalModel {
-            vertices: VertexArray { vertex: Vec::new(), attrib: Attrib::Unknown, morph_index: 0 }, 
-            indices: IndexArray { index: Vec::new() }, 
-            normals: NormalArray { normal: Vec::new() }, 
-            texcoords: TexCoordArray { texcoord: Vec::new() }, //UVArray { uv: Vec::new() },
-            material_ref: "".to_string(),
-            animation: Animation::new(),
-          });
-          
-          
-          let object = geometry_objects[j].clone();
-          let vertex = object.vertex;
-          let index = object.index;
-          let normal = object.normal;
-          
-          let mut transformed_vertex: Vec<[f32; 3]> = Vec::with_capacity(vertex.len());
-          for k in 0..vertex.len() {
-            let temp_vtx = Vector4::new(vertex[k][0], vertex[k][1], vertex[k][2], 1.0);
-            let mut vtx = transform*temp_vtx;
-            if metric.up == Z {
-              let value = vtx.y;
-              vtx.y = vtx.z;
-              vtx.z = value;
-            }
-            if metric.up == X {
-              let value = vtx.y;
-              vtx.y = vtx.x;
-              vtx.x = value;
-            }
-            let new_vtx = vtx;
-            transformed_vertex.push([new_vtx.x, new_vtx.y, new_vtx.z]);
-          }
-          
-          let mut transformed_normal: Vec<[f32; 3]> = Vec::with_capacity(normal.len());
-          for k in 0..normal.len() {
-            let temp_nrml = Vector4::new(normal[k][0], normal[k][1], normal[k][2], 1.0);
-            let mut nrml = transform*temp_nrml;
-            if metric.up == Z {
-              let value = nrml.y;
-              nrml.y = nrml.z;
-              nrml.z = value;
-            }
-            if metric.up == X {
-              let value = nrml.y;
-              nrml.y = nrml.x;
-              nrml.x = value;
-            }
-            
-            let new_nrml = nrml;
-            transformed_normal.push([new_nrml.x, new_nrml.y, new_nrml.z]);
-          }
-          
-          let tex_coord = geometry_objects[j].texcoord.clone();
-          
-          models[model_index].vertices = VertexArray { vertex: transformed_vertex, attrib: Attrib::Unknown, morph_index: 0 };
-          models[model_index].indices = IndexArray { index: index };
-          models[model_index].normals = NormalArray { normal: transformed_normal };
-          models[model_index].texcoords = TexCoordArray { texcoord: tex_coord };
-          if let Some(ref animation) = geometry_nodes[i].animation {
-            models[model_index].animation = animation.clone();
-          }
-          
-          for k in 0..geometry_nodes[i].materialref.len() {
-            if geometry_nodes[i].materialref[k].index == 0 {
-              models[model_index].material_ref = geometry_nodes[i].materialref[k].material_ref.clone();
-              break;
-            }
-          }
-          model_index+=1;
-          break;
-        }
+    Ok(OpengexPaser {
+      scene,
+      models,
+      meshes,
+      instances,
+      materials,
+      extensions,
+      metric,
+      warnings: warnings.warnings,
+    })
+  }
+  
+  // The file's units and axes, before the model was converted
+  pub fn get_metric(&self) -> &Metric {
+    &self.metric
+  }
+  
+  // What a lenient load skipped, in the order it was read
+  pub fn get_warnings(&self) -> &[Warning] {
+    &self.warnings
+  }
+  
+  pub fn get_scene(&self) -> &Scene {
+    &self.scene
+  }
+  
+  // The extensions of the file, or of a named node, object or material.
+  // Unnamed owners' extensions are kept with their node, mesh or material.
+  pub fn get_extensions(&self, owner: Owner) -> &[Extension] {
+    let extensions = match owner {
+      Owner::File => Some(&self.extensions),
+      Owner::Node(name) if !name.is_empty() => self.scene.find(name).map(|node| &node.extensions),
+      Owner::GeometryObject(name) if !name.is_empty() => {
+        self.meshes.iter().find(|mesh| mesh.object_ref == name).map(|mesh| &mesh.extensions)
+      },
+      Owner::Material(name) if !name.is_empty() => {
+        self.materials.iter().find(|material| material.material_ref == name).map(|material| &material.extensions)
+      },
+      _ => None,
+    };
+    extensions.map_or(&[], |extensions| extensions.as_slice())
+  }
+  
+  // Each material's name and extensions in file order, including the
+  // materials without a name
+  pub fn get_material_extensions(&self) -> Vec<(&str, &[Extension])> {
+    self.materials.iter().map(|material| (material.material_ref.as_str(), material.extensions.as_slice())).collect()
+  }
+  
+  // Every animation in the scene along with the node it animates
+  pub fn get_animations(&self) -> Vec<(&Node, &Animation)> {
+    let mut animations = Vec::new();
+    for node in self.scene.iter() {
+      for animation in &node.animations {
+        animations.push((node, animation));
       }
     }
-    
-    for mut node in geometry_nodes {
-      node.materialref.clear();
-      node.animation = None;
-    }
-    
-    for mut object in geometry_objects {
-      object.vertex.clear();
-      object.index.clear();
-      object.normal.clear();
-      object.texcoord.clear();
-    }
-    
-    OpengexPaser {
-      metric: metric,
-      models: models,
-      materials: materials,
-    }
+    animations
   }
   
-  pub fn get_vertex(&self) -> Vec<Vec<[f32; 3]>> {
-    let mut vertex: Vec<Vec<[f32; 3]>> = Vec::with_capacity(self.models.len());
+  // Each GeometryObject's mesh once, in object space
+  pub fn get_meshes(&self) -> &[Mesh] {
+    &self.meshes
+  }
+  
+  // Every GeometryNode with the mesh it draws, so shared meshes can be
+  // drawn with instancing rather than baked once per node. In the same
+  // order as get_vertex.
+  pub fn get_instances(&self) -> &[Instance] {
+    &self.instances
+  }
+  
+  pub fn get_vertex(&self) -> Vec<Vec<[Scalar; 3]>> {
+    let mut vertex: Vec<Vec<[Scalar; 3]>> = Vec::with_capacity(self.models.len());
     
-    for i in 0..self.models.len() {
-      let model = self.models[i].clone();
-      vertex.push(model.vertices.vertex);
+    for model in &self.models {
+      vertex.push(model.vertices.clone());
     }
     
     vertex
   }
   
-  pub fn get_normal(&self) -> Vec<Vec<[f32; 3]>> {
-    let mut normal: Vec<Vec<[f32; 3]>> = Vec::with_capacity(self.models.len());
+  pub fn get_normal(&self) -> Vec<Vec<[Scalar; 3]>> {
+    let mut normal: Vec<Vec<[Scalar; 3]>> = Vec::with_capacity(self.models.len());
     
-    for i in 0..self.models.len() {
-      let model = self.models[i].clone();
-      normal.push(model.normals.normal);
+    for model in &self.models {
+      normal.push(model.normals.clone());
     }
     normal
   }
   
+  // The skin of each model, in the same order as get_vertex
+  pub fn get_skins(&self) -> Vec<Option<&Skin>> {
+    self.models.iter().map(|model| model.skin.as_ref()).collect()
+  }
+  
+  // The morph targets of each model, in the same order as get_vertex
+  pub fn get_morphs(&self) -> Vec<&[Morph]> {
+    self.models.iter().map(|model| model.morphs.as_slice()).collect()
+  }
+  
+  // The morph weights of each model before any animation is applied
+  pub fn get_morph_weights(&self) -> Vec<&[MorphWeight]> {
+    self.models.iter().map(|model| model.morph_weights.as_slice()).collect()
+  }
+  
   pub fn get_index(&self) -> Vec<Vec<u32>> {
     let mut index: Vec<Vec<u32>> = Vec::with_capacity(self.models.len());
-    for i in 0..self.models.len() {
-      let model = self.models[i].clone();
-      index.push(model.indices.index);
+    for model in &self.models {
+      index.push(model.indices.clone());
     }
     index
   }
   
   
-  pub fn get_texcoords(&self) -> Vec<Vec<[f32; 2]>> {
-    let mut texcoords: Vec<Vec<[f32; 2]>> = Vec::with_capacity(self.models.len());
-    for i in 0..self.models.len() {
-      if self.models[i].texcoords.texcoord.len() > 0 {
-        let texcoord = self.models[i].texcoords.texcoord.clone();
-        texcoords.push(texcoord);
+  pub fn get_texcoords(&self) -> Vec<Vec<[Scalar; 2]>> {
+    let mut texcoords: Vec<Vec<[Scalar; 2]>> = Vec::with_capacity(self.models.len());
+    for model in &self.models {
+      if !model.texcoords.is_empty() {
+        texcoords.push(model.texcoords.clone());
       }
     }
     texcoords
   }
   
-  pub fn get_diffuse_textures(&self) -> Vec<(String, [f32; 3])> {
-    let mut textures: Vec<(String, [f32;3])> = Vec::new();
+  pub fn get_diffuse_textures(&self) -> Vec<(String, [Scalar; 3])> {
+    let mut textures: Vec<(String, [Scalar;3])> = Vec::new();
     
-    for i in 0..self.materials.len() {
-      textures.push(("".to_string(), self.materials[i].diffuse_colour));
-      for j in 0..self.materials[i].textures.len() {
-        match self.materials[i].textures[j].attrib {
-          Attrib::Diffuse => {
-            textures[i] = (self.materials[i].textures[j].texture.clone(), self.materials[i].diffuse_colour);
-          },
-          _ => {},
+    for (i, material) in self.materials.iter().enumerate() {
+      textures.push(("".to_string(), material.diffuse_colour));
+      for texture in &material.textures {
+        if let Attrib::Diffuse = texture.attrib {
+          textures[i] = (texture.texture.clone(), material.diffuse_colour);
         }
       }
     }
@@ -1318,14 +1473,11 @@ impl OpengexPaser {
   pub fn get_diffuse_texture(&self, material_ref: String) -> Option<String> {
     let mut texture: Option<String> = None;
     
-    for i in 0..self.materials.len() {
-      if self.materials[i].material_ref == material_ref {
-        for j in 0..self.materials[i].textures.len() {
-          match self.materials[i].textures[j].attrib {
-            Attrib::Diffuse => {
-              texture = Some(self.materials[i].textures[j].texture.clone());
-            },
-            _ => {},
+    for material in &self.materials {
+      if material.material_ref == material_ref {
+        for material_texture in &material.textures {
+          if let Attrib::Diffuse = material_texture.attrib {
+            texture = Some(material_texture.texture.clone());
           }
         }
       }
@@ -1335,11 +1487,924 @@ impl OpengexPaser {
   }
 }
 
+impl FromStr for OpengexPaser {
+  type Err = ParseError;
+  
+  fn from_str(source: &str) -> Result<OpengexPaser, ParseError> {
+    OpengexPaser::from_str_with_options(source, &ParseOptions::new())
+  }
+}
+
 #[cfg(test)]
 mod tests {
-    #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+  use super::*;
+  
+  #[test]
+  fn layout_does_not_change_the_model() {
+    let split = "GeometryNode $node1\n{\n\tObjectRef {ref {$geometry1}}\n\tTransform\n\t{\n\t\tfloat[16]\n\t\t{\n\t\t\t{1.0, 0.0, 0.0, 0.0,\n\t\t\t 0.0, 1.0, 0.0, 0.0,\n\t\t\t 0.0, 0.0, 1.0, 0.0,\n\t\t\t 1.0, 2.0, 3.0, 1.0}\n\t\t}\n\t}\n}\nGeometryObject $geometry1\n{\n\tMesh (primitive = \"triangles\")\n\t{\n\t\tVertexArray (attrib = \"position\")\n\t\t{\n\t\t\tfloat[3]\n\t\t\t{\n\t\t\t\t{0.0, 0.0, 0.0}, {1.0, 0.0, 0.0}, {0.0, 1.0, 0.0}\n\t\t\t}\n\t\t}\n\t\tIndexArray\n\t\t{\n\t\t\tunsigned_int32[3]\n\t\t\t{\n\t\t\t\t{0, 1, 2}\n\t\t\t}\n\t\t}\n\t}\n}\n";
+    let joined = "GeometryNode $node1 { ObjectRef { ref { $geometry1 } } Transform { float[16] { { 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 1, 2, 3, 1 } } } }
+                  GeometryObject $geometry1 { Mesh (primitive = \"triangles\") { VertexArray (attrib = \"position\") { float[3] { {0, 0, 0}, {1, 0, 0}, {0, 1, 0} } } IndexArray { unsigned_int32[3] { {0, 1, 2} } } } }";
+    
+    // Commented the way the OpenGEX specification's examples are
+    let commented = "// Exported from a test scene\r\nGeometryNode $node1\t\t// Triangle\r\n{ObjectRef/* the mesh */{ref{$geometry1}}Transform{float[16]{{1,0,0,0,0,1,0,0,0,0,1,0,1,2,3,1}}}}
+    /* GeometryObject $geometry2 {
+         Mesh {}
+       } */
+    GeometryObject $geometry1{Mesh(primitive=\"triangles\"){VertexArray(attrib=\"position\"){float[3]\t// 3
+    {{0,0,0},{1,0,0},{0,1,0}}}IndexArray{unsigned_int32[3]/**/{{0,1,2}}}}}";
+    
+    let split = OpengexPaser::from_str(split).unwrap();
+    let joined = OpengexPaser::from_str(joined).unwrap();
+    let commented = OpengexPaser::from_str(commented).unwrap();
+    
+    assert_eq!(split.get_vertex(), vec![vec![[1.0, 2.0, 3.0], [2.0, 2.0, 3.0], [1.0, 3.0, 3.0]]]);
+    assert_eq!(split.get_vertex(), joined.get_vertex());
+    assert_eq!(split.get_index(), joined.get_index());
+    assert_eq!(split.get_vertex(), commented.get_vertex());
+    assert_eq!(split.get_index(), commented.get_index());
+  }
+  
+  #[test]
+  fn reports_errors_with_locations() {
+    match OpengexPaser::load("./does/not/exist.ogex") {
+      Err(ParseError::Io { location, .. }) => assert_eq!(location.path.unwrap(), Path::new("./does/not/exist.ogex")),
+      _ => panic!("expected an io error"),
+    }
+    
+    match OpengexPaser::from_bytes(b"GeometryNode $node1 {\n  ObjectRef {ref {$geometry1}}\n}") {
+      Err(ParseError::UnresolvedReference { location, reference }) => {
+        assert_eq!(reference, "$geometry1");
+        assert_eq!((location.line, location.column), (2, 3));
+      },
+      _ => panic!("expected an unresolved reference"),
+    }
+    
+    match OpengexPaser::from_bytes(b"GeometryNode $node1 {\n  Transform {float[3] {{0, 0, 0}}}\n}") {
+      Err(ParseError::TypeMismatch { location, found, .. }) => {
+        assert_eq!(found, "float[3]");
+        assert_eq!((location.line, location.column), (2, 14));
+      },
+      _ => panic!("expected a type mismatch"),
     }
+    
+    match OpengexPaser::from_bytes(b"Metric (key = \"up\") {string {\"\xff\"}}") {
+      Err(ParseError::Syntax { location, .. }) => assert_eq!((location.line, location.column), (1, 31)),
+      _ => panic!("expected a syntax error"),
+    }
+  }
+  
+  #[test]
+  fn loads_from_memory() {
+    let source = "GeometryNode {ObjectRef {ref {$geometry1}}}\nGeometryObject $geometry1 {Mesh {VertexArray (attrib = \"position\") {float[3] {{1, 2, 3}}}}}";
+    
+    let from_str = OpengexPaser::from_str(source).unwrap();
+    let from_bytes = OpengexPaser::from_bytes(source.as_bytes()).unwrap();
+    let from_reader = OpengexPaser::from_reader(std::io::Cursor::new(source)).unwrap();
+    
+    assert_eq!(from_str.get_vertex(), vec![vec![[1.0, 2.0, 3.0]]]);
+    assert_eq!(from_bytes.get_vertex(), from_str.get_vertex());
+    assert_eq!(from_reader.get_vertex(), from_str.get_vertex());
+    
+    let error = OpengexPaser::from_str("GeometryNode {").err().unwrap();
+    assert_eq!(error.location().path, None);
+  }
+  
+  #[test]
+  fn nested_nodes_compose_parent_transforms() {
+    let source = "
+      GeometryNode $car {
+        Name {string {\"Car\"}}
+        ObjectRef {ref {$body}}
+        Transform {float[16] {{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 10, 0, 0, 1}}}
+        
+        GeometryNode $wheel {
+          Name {string {\"Wheel\"}}
+          ObjectRef {ref {$tyre}}
+          Transform {float[16] {{2, 0, 0, 0, 0, 2, 0, 0, 0, 0, 2, 0, 0, 1, 0, 1}}}
+        }
+      }
+      GeometryObject $body {Mesh {VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}}}}}
+      GeometryObject $tyre {Mesh {VertexArray (attrib = \"position\") {float[3] {{1, 0, 0}}}}}";
+    
+    let model = OpengexPaser::from_str(source).unwrap();
+    let scene = model.get_scene();
+    
+    assert_eq!(scene.nodes.len(), 1);
+    let wheel = scene.find("$wheel").unwrap();
+    assert_eq!(wheel.name, "Wheel");
+    assert_eq!(wheel.geometry().unwrap().object_ref, "$tyre");
+    assert_eq!(scene.nodes[0].children[0].node_ref, "$wheel");
+    assert_eq!(wheel.world_transform.w.truncate(), cgmath::Vector3::new(10.0, 1.0, 0.0));
+    assert_eq!(scene.iter().map(|node| node.name.as_str()).collect::<Vec<_>>(), vec!["Car", "Wheel"]);
+    
+    assert_eq!(model.get_vertex(), vec![vec![[10.0, 0.0, 0.0]], vec![[12.0, 1.0, 0.0]]]);
+  }
+  
+  #[test]
+  fn transform_stack_is_composed_in_file_order() {
+    let source = "
+      GeometryNode $node1 {
+        ObjectRef {ref {$geometry1}}
+        Translation %xpos (kind = \"x\") {float {2}}
+        Translation %ypos (kind = \"y\") {float {2}}
+        Rotation %zrot (kind = \"z\") {float {1.5707963}}
+        Scale {float[3] {{2, 2, 2}}}
+        Scale %shrink (object = true) {float[3] {{0.5, 0.5, 0.5}}}
+      }
+      GeometryObject $geometry1 {Mesh {VertexArray (attrib = \"position\") {float[3] {{2, 0, 0}}}}}";
+    
+    let model = OpengexPaser::from_str(source).unwrap();
+    let node = model.get_scene().find("$node1").unwrap();
+    
+    let names: Vec<&str> = node.transforms.iter().map(|transform| transform.name.as_str()).collect();
+    assert_eq!(names, vec!["%xpos", "%ypos", "%zrot", "", "%shrink"]);
+    match node.transforms[2].value {
+      TransformValue::Rotation(Rotation::Z(angle)) => assert!((angle.to_f32() - std::f32::consts::FRAC_PI_2).abs() < 1e-6),
+      _ => panic!("expected a z rotation"),
+    }
+    assert!(node.transforms[4].object);
+    
+    let vertex = model.get_vertex()[0][0];
+    assert!((vertex[0] - 2.0).abs() < 1e-5);
+    assert!((vertex[1] - 4.0).abs() < 1e-5);
+    assert!(vertex[2].abs() < 1e-5);
+  }
+  
+  #[test]
+  fn rotation_kinds() {
+    let source = "Node $node1 {
+      Rotation (kind = \"axis\") {float[4] {{3.1415927, 0, 0, 1}}}
+      Rotation (kind = \"quaternion\") {float[4] {{0, 0, 1, 0}}}
+    }";
+    
+    let model = OpengexPaser::from_str(source).unwrap();
+    let node = &model.get_scene().nodes[0];
+    
+    // Rotating half a turn about z then half a turn about z again is no rotation
+    let x = node.local_transform.x;
+    assert!((x.x - 1.0).abs() < 1e-5 && x.y.abs() < 1e-5);
+    
+    match OpengexPaser::from_str("Node {Scale (kind = \"w\") {float {1}}}") {
+      Err(ParseError::InvalidValue { .. }) => {},
+      _ => panic!("expected an invalid value"),
+    }
+  }
+  
+  #[test]
+  fn exposes_animations() {
+    let model = OpengexPaser::load("examples/data/testobject/ObjectTranslationAnimation.ogex").unwrap();
+    let animations = model.get_animations();
+    assert_eq!(animations.len(), 1);
+    
+    let (node, animation) = animations[0];
+    assert_eq!(node.name, "Icosphere");
+    assert_eq!(animation.begin, Some(-1.0/24.0));
+    assert_eq!(animation.end, Some(4.125));
+    
+    let track = &animation.tracks[0];
+    assert_eq!(track.target_ref, "%zpos");
+    assert_eq!(track.target, TargetType::Zpos);
+    assert_eq!(track.time.curve, Curve::Bezier);
+    assert_eq!(track.value.values().unwrap().floats[0], 2.0);
+    assert_eq!(track.value.minus_control().unwrap().key_type, KeyType::MinusControl);
+    assert_eq!(track.key_count(), track.value.plus_control().unwrap().floats.len());
+  }
+  
+  #[test]
+  fn samples_animations() {
+    let model = OpengexPaser::load("examples/data/testobject/ObjectTranslationAnimation.ogex").unwrap();
+    let (node, animation) = model.get_animations()[0];
+    
+    // Keys land exactly on their values, and the range clamps the time
+    assert!((animation.sample(node, 2.0416666).w.z - 3.0).abs() < 1e-4);
+    assert!((animation.sample(node, 100.0).w.z - 2.0).abs() < 1e-4);
+    let between = animation.sample(node, 1.1).w.z;
+    assert!(between > 2.5 && between < 2.6);
+    
+    let source = "Metric (key = \"time\") {float {0.5}}
+    Node {
+      Translation %xpos (kind = \"x\") {float {0}}
+      Animation {
+        Track (target = %xpos) {
+          Time {Key {float {0, 2, 4}}}
+          Value (curve = \"tcb\") {
+            Key {float {0, 10, 0}}
+            Key (kind = \"tension\") {float {0, 0, 0}}
+            Key (kind = \"continuity\") {float {0, 0, 0}}
+            Key (kind = \"bias\") {float {0, 0, 0}}
+          }
+        }
+      }
+    }";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let node = &model.get_scene().nodes[0];
+    let animation = &node.animations[0];
+    assert_eq!(animation.tracks[0].value.curve, Curve::Tcb);
+    // Key times stay in the file's units unless asked otherwise
+    assert_eq!(animation.time_scale, 0.5);
+    assert_eq!(animation.tracks[0].time.values().unwrap().floats, vec![0.0, 2.0, 4.0]);
+    
+    // One second is two units of file time, the middle key
+    assert!((animation.sample(node, 1.0).w.x - 10.0).abs() < 1e-5);
+    let rising = animation.sample(node, 0.5).w.x;
+    assert!(rising > 5.0 && rising < 10.0);
+  }
+  
+  #[test]
+  fn resolves_track_targets() {
+    let model = OpengexPaser::load("examples/data/testobject/ObjectRotationAnimation.ogex").unwrap();
+    let (_, animation) = model.get_animations()[0];
+    assert_eq!(animation.tracks[0].target, TargetType::Yrot);
+    
+    let source = "Node {
+      Translation %offset {float[3] {{0, 0, 0}}}
+      Rotation %spin (kind = \"quaternion\") {float[4] {{0, 0, 0, 1}}}
+      Animation {
+        Track (target = %offset) {
+          Time {Key {float {0, 1}}}
+          Value {Key {float[3] {{0, 0, 0}, {2, 4, 6}}}}
+        }
+        Track (target = %spin) {
+          Time {Key {float {0, 1}}}
+          Value {Key {float[4] {{0, 0, 0, 1}, {0, 0, 1, 0}}}}
+        }
+      }
+    }";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let node = &model.get_scene().nodes[0];
+    let animation = &node.animations[0];
+    assert_eq!(animation.tracks[0].target, TargetType::Xyzpos);
+    assert_eq!(animation.tracks[1].target, TargetType::QuaternionRot);
+    assert_eq!(animation.tracks[0].sample_value(0.5), Some(TrackValue::Vector(Vector3::new(1.0, 2.0, 3.0))));
+    match animation.tracks[1].sample_value(0.5) {
+      Some(TrackValue::Quaternion(q)) => assert!((q.s - q.v.z).abs() < 1e-5 && (q.s*q.s + q.v.z*q.v.z - 1.0).abs() < 1e-5),
+      value => panic!("expected a quaternion, found {:?}", value),
+    }
+    
+    // The translation is the only part of the animated transform that moves
+    assert_eq!(animation.sample(node, 1.0).w.truncate(), Vector3::new(2.0, 4.0, 6.0));
+    
+    match OpengexPaser::from_str("Node {Animation {Track (target = %missing) {}}}") {
+      Err(ParseError::UnresolvedReference { reference, .. }) => assert_eq!(reference, "%missing"),
+      _ => panic!("expected an unresolved reference"),
+    }
+    let source = "Node {Translation %x (kind = \"x\") {float {0}} Animation {Track (target = %x) {Time {Key {float {0}}} Value {Key {float[3] {{1, 2, 3}}}}}}}";
+    match OpengexPaser::from_str(source) {
+      Err(ParseError::TypeMismatch { expected, found, .. }) => assert_eq!((expected.as_str(), found.as_str()), ("float", "float[3]")),
+      _ => panic!("expected a type mismatch"),
+    }
+  }
+  
+  #[test]
+  fn reads_skins() {
+    let source = "BoneNode $root {
+      BoneNode $tip {Translation {float[3] {{0, 1, 0}}}}
+    }
+    GeometryNode {ObjectRef {ref {$mesh}}}
+    GeometryObject $mesh {
+      Mesh {
+        VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}, {0, 1, 0}}}
+        Skin {
+          Transform {float[16] {{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 2, 1}}}
+          Skeleton {
+            BoneRefArray {ref {$root, $tip}}
+            Transform {float[16] {{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1},
+                                  {1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, -1, 0, 1}}}
+          }
+          BoneCountArray {unsigned_int16 {1, 2}}
+          BoneIndexArray {unsigned_int8 {0, 0, 1}}
+          BoneWeightArray {float {1, 0.25, 0.75}}
+        }
+      }
+    }";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let skins = model.get_skins();
+    let skin = skins[0].unwrap();
+    assert_eq!(skin.bind_transform.w.z, 2.0);
+    assert_eq!(skin.skeleton.bone_refs, vec!["$root", "$tip"]);
+    assert_eq!(skin.skeleton.bind_transforms[1].w.y, -1.0);
+    assert_eq!(skin.weights[0], vec![BoneWeight { bone: 0, weight: 1.0 }]);
+    assert_eq!(skin.weights[1][1], BoneWeight { bone: 1, weight: 0.75 });
+    
+    match OpengexPaser::from_str(&source.replace("{$root, $tip}", "{$root, $mesh}")) {
+      Err(ParseError::TypeMismatch { found, .. }) => assert_eq!(found, "GeometryObject $mesh"),
+      _ => panic!("expected a bone that isn't a BoneNode"),
+    }
+    match OpengexPaser::from_str(&source.replace("{$root, $tip}", "{$root, $leaf}")) {
+      Err(ParseError::UnresolvedReference { reference, .. }) => assert_eq!(reference, "$leaf"),
+      _ => panic!("expected an unresolved bone"),
+    }
+    match OpengexPaser::from_str(&source.replace("{1, 2}", "{1, 1}")) {
+      Err(ParseError::InvalidValue { .. }) => {},
+      _ => panic!("expected mismatched bone counts"),
+    }
+  }
+  
+  #[test]
+  fn reads_morph_targets() {
+    let source = "GeometryNode {
+      ObjectRef {ref {$face}}
+      MorphWeight %smile (index = 1) {float {0.25}}
+      Animation {
+        Track (target = %smile) {
+          Time {Key {float {0, 1}}}
+          Value {Key {float {0, 1}}}
+        }
+      }
+    }
+    GeometryObject $face {
+      Morph (index = 1) {Name {string {\"Smile\"}}}
+      Morph (index = 2, base = 1) {Name {string {\"Grin\"}}}
+      Mesh {
+        VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}, {1, 0, 0}}}
+        VertexArray (attrib = \"position\", morph = 1) {float[3] {{0, 1, 0}, {1, 0, 0}}}
+        VertexArray (attrib = \"position\", morph = 2) {float[3] {{0, 3, 0}, {1, 0, 0}}}
+      }
+    }";
+    let model = OpengexPaser::from_str(source).unwrap();
+    assert_eq!(model.get_vertex()[0], vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]]);
+    
+    let morphs = model.get_morphs()[0];
+    assert_eq!(morphs.len(), 2);
+    assert_eq!(morphs[0].name, "Smile");
+    assert_eq!(morphs[0].position_deltas[0], [0.0, 1.0, 0.0]);
+    assert!(morphs[0].normal_deltas.is_empty());
+    // Grin is relative to Smile rather than the mesh
+    assert_eq!(morphs[1].base, Some(1));
+    assert_eq!(morphs[1].position_deltas[0], [0.0, 2.0, 0.0]);
+    
+    assert_eq!(model.get_morph_weights()[0][0].weight, 0.25);
+    let (node, animation) = model.get_animations()[0];
+    assert_eq!(animation.tracks[0].target, TargetType::MorphWeight(1));
+    assert_eq!(animation.sample_morph_weights(node, 0.5)[0].weight, 0.5);
+  }
+  
+  #[test]
+  fn accepts_any_index_and_float_type() {
+    let source = "GeometryNode {ObjectRef {ref {$mesh}}}
+    GeometryObject $mesh {
+      Mesh {
+        VertexArray (attrib = \"position\") {double[3] {{0, 0, 0}, {1, 0, 0}, {0, 1, 0}}}
+        VertexArray (attrib = \"texcoord\") {half[2] {{0x0000, 0x3c00}, {0.5, 0}, {1, 1}}}
+        IndexArray {unsigned_int16[3] {{0, 1, 2}}}
+      }
+    }";
+    let model = OpengexPaser::from_str(source).unwrap();
+    assert_eq!(model.get_vertex()[0][1], [1.0, 0.0, 0.0]);
+    assert_eq!(model.get_texcoords()[0][0], [0.0, 1.0]);
+    assert_eq!(model.get_index()[0], vec![0, 1, 2]);
+  }
+  
+  #[test]
+  fn extensions_are_attached_to_their_owner() {
+    #[derive(Debug, PartialEq)]
+    struct EditorLayer(String);
+    
+    let source = "Extension (applic = \"Editor\", type = \"Version\") {unsigned_int32 {3}}
+    Node $node1 {
+      Extension (applic = \"Editor\", type = \"Layer\") {string {\"props\"}}
+      Extension (applic = \"Other\", type = \"Layer\") {string {\"ignored\"}}
+    }
+    Material $material1 {
+      Extension (applic = \"Editor\", type = \"Layer\") {string {\"shared\"}}
+    }";
+    let document: Document = source.parse().unwrap();
+    
+    let mut registry = ExtensionRegistry::new();
+    registry.register("Editor", "Layer", |structure| Ok(EditorLayer(get_string(structure)?.to_string())));
+    registry.register("Editor", "Version", |structure| {
+      let data = structure.primitive().and_then(|data| data.unsigned_int32s()).ok_or_else(|| type_mismatch(structure, "unsigned_int32"))?;
+      Ok(data[0])
+    });
+    let model = OpengexPaser::from_document_with_extensions(&document, &registry).unwrap();
+    
+    let node = model.get_scene().find("$node1").unwrap();
+    assert_eq!(node.extensions.len(), 1);
+    assert_eq!(node.extensions[0].get::<EditorLayer>(), Some(&EditorLayer("props".to_string())));
+    assert_eq!(node.extensions[0].get::<u32>(), None);
+    assert_eq!(model.get_extensions(Owner::Node("$node1")).len(), 1);
+    assert_eq!(model.get_extensions(Owner::Material("$material1"))[0].get::<EditorLayer>().unwrap().0, "shared");
+    assert_eq!(model.get_extensions(Owner::File)[0].get::<u32>(), Some(&3));
+    // Owners are looked up by their kind as well as their name
+    assert!(model.get_extensions(Owner::Material("$node1")).is_empty());
+    assert!(model.get_extensions(Owner::GeometryObject("$material1")).is_empty());
+    
+    // Owners don't need a name to keep their extensions
+    let source = "GeometryObject {
+      Mesh {VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}}}}
+      Extension (applic = \"Editor\", type = \"Layer\") {string {\"terrain\"}}
+    }
+    Material {Extension (applic = \"Editor\", type = \"Layer\") {string {\"paint\"}}}";
+    let unnamed: Document = source.parse().unwrap();
+    let model = OpengexPaser::from_document_with_extensions(&unnamed, &registry).unwrap();
+    assert_eq!(model.get_meshes()[0].extensions[0].get::<EditorLayer>().unwrap().0, "terrain");
+    assert_eq!(model.get_material_extensions()[0].1[0].get::<EditorLayer>().unwrap().0, "paint");
+    assert!(model.get_extensions(Owner::GeometryObject("")).is_empty());
+    
+    // Registries can be shared between loading threads
+    fn is_send_sync<T: Send + Sync>(_: &T) {}
+    is_send_sync(&registry);
+    
+    // Handlers can reject what they're given
+    let mut registry = ExtensionRegistry::new();
+    registry.register("Editor", "Layer", get_float);
+    assert!(OpengexPaser::from_document_with_extensions(&document, &registry).is_err());
+    assert!(OpengexPaser::from_document(&document).unwrap().get_extensions(Owner::Node("$node1")).is_empty());
+  }
+  
+  #[test]
+  fn loads_at_scalar_precision() {
+    let source = "GeometryNode $node1 {ObjectRef {ref {$geometry1}} Translation {double[3] {{1000000.5, 0, 0}}}}
+                  GeometryObject $geometry1 {Mesh {VertexArray (attrib = \"position\") {double[3] {{0.001, 0, 0}}} IndexArray {unsigned_int8[3] {{0, 0, 0}}}}}";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let vertex = model.get_vertex()[0][0];
+    let translation: Scalar = 1_000_000.5;
+    let offset: Scalar = 0.001;
+    assert_eq!(vertex, [translation + offset, 0.0, 0.0]);
+    #[cfg(feature = "f64")]
+    assert!((vertex[0] - 1_000_000.501).abs() < 1e-9);
+    
+    assert_eq!(vertex.to_f32(), [1_000_000.5, 0.0, 0.0]);
+    assert_eq!(model.get_vertex()[0].to_f32(), vec![[1_000_000.5, 0.0, 0.0]]);
+    let node = model.get_scene().find("$node1").unwrap();
+    assert_eq!(node.world_transform.to_f32().w, cgmath::Vector4::new(1_000_000.5, 0.0, 0.0, 1.0));
+  }
+  
+  #[test]
+  fn lenient_loads_warn_and_strict_loads_fail() {
+    let model = OpengexPaser::load("examples/data/cube.ogex").unwrap();
+    let warning = &model.get_warnings()[0];
+    assert_eq!(warning.to_string(), "examples/data/cube.ogex:92:1: LightObject is not supported");
+    assert_eq!(model.get_vertex().len(), 1);
+    
+    match OpengexPaser::load_with_options("examples/data/cube.ogex", &ParseOptions::strict()) {
+      Err(ParseError::Unsupported { location, message }) => {
+        assert_eq!((location.line, location.column), (92, 1));
+        assert_eq!(message, "LightObject is not supported");
+      },
+      _ => panic!("expected an unsupported structure"),
+    }
+    
+    let source = "Metric (key = \"mass\") {float {0.01}}
+                  GeometryNode {MaterialRef (index = \"one\") {ref {$material1}} Gizmo {}}
+                  GeometryObject $geometry1 {Mesh {VertexArray (attrib = \"tangent\") {float[3] {}}}}
+                  Material $material1 {Texture (attrib = \"emission\") {string {\"glow.png\"}}}";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let warnings: Vec<String> = model.get_warnings().iter().map(|warning| warning.message.clone()).collect();
+    assert_eq!(warnings, vec![
+      "Unknown Metric key mass",
+      "Unknown structure Gizmo in GeometryNode",
+      "Expected an unsigned integer for the index of MaterialRef",
+      "VertexArray attrib tangent is not supported",
+      "Texture attrib emission is not supported",
+    ]);
+    assert!(OpengexPaser::from_str_with_options(source, &ParseOptions::strict()).is_err());
+    
+    // So are properties that aren't read and extensions without a handler
+    let sources = [
+      "Node (bogus = 1) {}",
+      "GeometryObject {Mesh (lod = 1) {IndexArray (front = \"cw\", restart = 0) {unsigned_int16[3] {{0, 0, 0}}}}}",
+      "Extension (applic = \"Editor\", type = \"Layer\") {string {\"props\"}}",
+    ];
+    let model = OpengexPaser::from_str(&sources.join("\n")).unwrap();
+    let warnings: Vec<String> = model.get_warnings().iter().map(|warning| warning.message.clone()).collect();
+    assert_eq!(warnings, vec![
+      "Unknown property bogus in Node",
+      "Mesh property lod is not supported",
+      "IndexArray property front is not supported",
+      "IndexArray property restart is not supported",
+      "No handler for Extension applic Editor type Layer",
+    ]);
+    for source in sources.iter() {
+      match OpengexPaser::from_str_with_options(source, &ParseOptions::strict()) {
+        Err(ParseError::Unsupported { .. }) => {},
+        _ => panic!("expected {} to fail a strict load", source),
+      }
+    }
+    
+    // Nothing is lost from a file the crate fully supports
+    let source = "Metric (key = \"distance\") {float {1}} Node $node1 {Translation {float[3] {{1, 2, 3}}}}";
+    assert!(OpengexPaser::from_str_with_options(source, &ParseOptions::strict()).unwrap().get_warnings().is_empty());
+  }
+  
+  #[test]
+  fn validates_against_the_specification() {
+    for path in &["examples/data/cube.ogex", "examples/data/Holostand/Holostand.ogex",
+                  "examples/data/testobject/ObjectRotationAnimation.ogex"] {
+      let document = openddl::Document::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+      assert_eq!(validate(&document), Vec::new(), "{}", path);
+    }
+    
+    let source = "Metric (key = \"up\") {string {\"w\"}}
+                  GeometryNode $node1 {
+                    ObjectRef {ref {$material1}} Gizmo {}
+                    Translation %xfm {float[3] {{1, 2, 3}}}
+                    Animation {Track (target = %xfm) {Time {Key {float {0, 1}}} Value {Key {float[3] {{0, 0, 0}}}}}}
+                  }
+                  GeometryObject $geometry1 {
+                    Mesh {
+                      VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}, {1, 0, 0}, {0, 1, 0}}}
+                      VertexArray (attrib = \"texcoord\") {float[4] {{0, 0, 0, 0}}}
+                      IndexArray {unsigned_int32[3] {{0, 1, 3}}}
+                    }
+                  }
+                  Material $material1 {Color {float[3] {{1, 1, 1}}}}";
+    let document = openddl::Document::from_str(source).unwrap();
+    let violations: Vec<String> = validate(&document).iter().map(|violation| violation.message.clone()).collect();
+    assert_eq!(violations, vec![
+      "w is not a valid up direction",
+      "ObjectRef refers to a Material rather than a GeometryObject",
+      "Unknown structure Gizmo in GeometryNode",
+      "Key has 1 values but the Track has 2 keys",
+      "VertexArray has 1 vertices but the Mesh has 3",
+      "Expected float[2] or float[3] data in VertexArray but found float[4]",
+      "Index 3 is out of range for 3 vertices",
+      "Color is missing its attrib property",
+    ]);
+  }
+  
+  #[test]
+  fn normals_use_the_inverse_transpose() {
+    let source = "
+      GeometryNode {
+        ObjectRef {ref {$ramp}}
+        Translation {float[3] {{10, 0, 0}}}
+        Scale {float[3] {{2, 1, 1}}}
+      }
+      GeometryObject $ramp {
+        Morph (index = 1) {}
+        Mesh {
+          VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}}}
+          VertexArray (attrib = \"normal\") {float[3] {{1, 1, 0}}}
+          VertexArray (attrib = \"normal\", morph = 1) {float[3] {{0, 0, 3}}}
+        }
+      }";
+    
+    let model = OpengexPaser::from_str(source).unwrap();
+    let normal = model.get_normal()[0][0];
+    let expected = [1.0/(5.0 as Scalar).sqrt(), 2.0/(5.0 as Scalar).sqrt(), 0.0];
+    for axis in 0..3 {
+      assert!((normal[axis] - expected[axis]).abs() < 1e-6, "{:?}", normal);
+    }
+    
+    // Morph deltas are taken between unit length normals
+    let delta = model.get_morphs()[0][0].normal_deltas[0];
+    let target = [normal[0] + delta[0], normal[1] + delta[1], normal[2] + delta[2]];
+    assert!(target.iter().zip(&[0.0, 0.0, 1.0]).all(|(a, b)| (a - b).abs() < 1e-6), "{:?}", target);
+  }
+  
+  #[test]
+  fn converts_to_the_requested_axes() {
+    let source = "Metric (key = \"up\") {string {\"z\"}}
+      GeometryNode {
+        ObjectRef {ref {$triangle}}
+        Translation %zpos (kind = \"z\") {float {5}}
+        Animation {
+          Track (target = %zpos) {Time {Key {float {0, 1}}} Value {Key {float {5, 7}}}}
+        }
+      }
+      GeometryObject $triangle {
+        Mesh {
+          VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}, {1, 0, 0}, {0, 1, 0}}}
+          VertexArray (attrib = \"normal\") {float[3] {{0, 0, 1}, {0, 0, 1}, {0, 0, 1}}}
+          IndexArray {unsigned_int32[3] {{0, 1, 2}}}
+        }
+      }";
+    
+    // By default the file's own axes are kept
+    let model = OpengexPaser::from_str(source).unwrap();
+    assert_eq!(model.get_vertex()[0], vec![[0.0, 0.0, 5.0], [1.0, 0.0, 5.0], [0.0, 1.0, 5.0]]);
+    assert_eq!(model.get_animations()[0].1.tracks[0].target, TargetType::Zpos);
+    
+    // Z up is turned to y up, so +y in the file ends up as -z
+    let options = ParseOptions {
+      conversion: Some(ConversionOptions::new()),
+      ..ParseOptions::new()
+    };
+    let model = OpengexPaser::from_str_with_options(source, &options).unwrap();
+    assert_eq!(model.get_vertex()[0], vec![[0.0, 5.0, 0.0], [1.0, 5.0, 0.0], [0.0, 5.0, -1.0]]);
+    assert_eq!(model.get_normal()[0][0], [0.0, 1.0, 0.0]);
+    assert_eq!(model.get_index()[0], vec![0, 1, 2]);
+    let (node, animation) = model.get_animations()[0];
+    assert_eq!(animation.tracks[0].target, TargetType::Ypos);
+    assert!((animation.sample(node, 1.0).w.y - 7.0).abs() < 1e-6);
+    
+    // Files that don't give an up direction are z up
+    let unmarked = source.replacen("Metric (key = \"up\") {string {\"z\"}}", "", 1);
+    assert_eq!(OpengexPaser::from_str_with_options(&unmarked, &options).unwrap().get_vertex(), model.get_vertex());
+    
+    // Mirroring into a left handed system rewinds the triangles
+    let options = ParseOptions {
+      conversion: Some(ConversionOptions::left_handed()),
+      ..ParseOptions::new()
+    };
+    let model = OpengexPaser::from_str_with_options(source, &options).unwrap();
+    assert_eq!(model.get_vertex()[0], vec![[0.0, 5.0, 0.0], [1.0, 5.0, 0.0], [0.0, 5.0, 1.0]]);
+    assert_eq!(model.get_index()[0], vec![0, 2, 1]);
+    
+    // Other axes can be asked for too
+    let options = ParseOptions {
+      conversion: Some(ConversionOptions { up: Axis::Z, forward: Axis::Y, handedness: Handedness::Left }),
+      ..ParseOptions::new()
+    };
+    let model = OpengexPaser::from_str_with_options(source, &options).unwrap();
+    assert_eq!(model.get_vertex()[0], vec![[0.0, 0.0, 5.0], [-1.0, 0.0, 5.0], [0.0, 1.0, 5.0]]);
+    assert_eq!(model.get_index()[0], vec![0, 2, 1]);
+    
+    // A forward direction in the file turns the model to face forward when
+    // it's converted, and is left alone otherwise
+    let source = source.replacen("Metric", "Metric (key = \"forward\") {string {\"x\"}} Metric", 1);
+    let options = ParseOptions {
+      conversion: Some(ConversionOptions::new()),
+      ..ParseOptions::new()
+    };
+    let model = OpengexPaser::from_str_with_options(&source, &options).unwrap();
+    assert_eq!(model.get_vertex()[0][1], [0.0, 5.0, -1.0]);
+    let model = OpengexPaser::from_str(&source).unwrap();
+    assert_eq!(model.get_vertex()[0][1], [1.0, 0.0, 5.0]);
+  }
+  
+  #[test]
+  fn converts_metric_units() {
+    let source = "Metric (key = \"distance\") {float {0.01}}
+      Metric (key = \"angle\") {float {0.017453292}}
+      Metric (key = \"time\") {float {0.001}}
+      GeometryNode {
+        ObjectRef {ref {$box}}
+        Translation %xpos (kind = \"x\") {float {100}}
+        Rotation (kind = \"y\") {float {90}}
+        Animation (begin = 0, end = 500) {
+          Track (target = %xpos) {Time {Key {float {0, 500}}} Value {Key {float {100, 300}}}}
+        }
+      }
+      GeometryObject $box {Mesh {VertexArray (attrib = \"position\") {float[3] {{50, 0, 0}}}}}";
+    
+    // By default the file's distances and times are kept, and only angles
+    // are converted
+    let model = OpengexPaser::from_str(source).unwrap();
+    let (node, animation) = model.get_animations()[0];
+    assert!((model.get_vertex()[0][0][0] - 100.0).abs() < 1e-3 && (model.get_vertex()[0][0][2] + 50.0).abs() < 1e-3);
+    assert_eq!(animation.time_scale.to_f32(), 0.001);
+    assert!((animation.sample(node, 0.5).w.x - 300.0).abs() < 1e-3);
+    match node.transforms[1].value {
+      TransformValue::Rotation(Rotation::Y(angle)) => assert!((angle - std::f64::consts::FRAC_PI_2 as Scalar).abs() < 1e-5),
+      ref value => panic!("expected a y rotation, not {:?}", value),
+    }
+    
+    let metric = model.get_metric();
+    assert_eq!((metric.distance.to_f32(), metric.time.to_f32()), (0.01, 0.001));
+    assert_eq!((metric.up, metric.forward), (Axis::Z, None));
+    
+    // Centimeters, degrees and milliseconds become meters, radians and seconds
+    let options = ParseOptions {
+      units: Some(Units::new()),
+      ..ParseOptions::new()
+    };
+    let model = OpengexPaser::from_str_with_options(source, &options).unwrap();
+    let vertex = model.get_vertex()[0][0];
+    assert!((vertex[0] - 1.0).abs() < 1e-5 && vertex[1].abs() < 1e-5 && (vertex[2] + 0.5).abs() < 1e-5, "{:?}", vertex);
+    let (node, animation) = model.get_animations()[0];
+    assert!((animation.range().1 - 0.5).abs() < 1e-6);
+    assert!((animation.sample(node, 0.5).w.x - 3.0).abs() < 1e-5);
+    
+    // Or any other unit, like centimeters
+    let options = ParseOptions {
+      units: Some(Units { distance: 0.01, ..Units::new() }),
+      ..ParseOptions::new()
+    };
+    let model = OpengexPaser::from_str_with_options(source, &options).unwrap();
+    assert!((model.get_vertex()[0][0][0] - 100.0).abs() < 1e-3);
+  }
+  
+  #[test]
+  fn shared_objects_are_instanced() {
+    let source = "
+      GeometryNode $tree1 {ObjectRef {ref {$tree}} MaterialRef {ref {$bark}} Translation {float[3] {{1, 0, 0}}}}
+      GeometryNode $tree2 {ObjectRef {ref {$tree}} MaterialRef {ref {$bark}} Translation {float[3] {{2, 0, 0}}}}
+      GeometryNode $rock1 {ObjectRef {ref {$rock}}}
+      GeometryObject $tree {Mesh {VertexArray (attrib = \"position\") {float[3] {{0, 1, 0}}}}}
+      GeometryObject $rock {Mesh {VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}}}}}
+      Material $bark {}";
+    
+    let model = OpengexPaser::from_str(source).unwrap();
+    let meshes = model.get_meshes();
+    assert_eq!(meshes.iter().map(|mesh| mesh.object_ref.as_str()).collect::<Vec<_>>(), vec!["$tree", "$rock"]);
+    assert_eq!(meshes[0].positions, vec![[0.0, 1.0, 0.0]]);
+    
+    let instances = model.get_instances();
+    assert_eq!(instances.iter().map(|instance| (instance.node_ref.as_str(), instance.mesh)).collect::<Vec<_>>(),
+               vec![("$tree1", 0), ("$tree2", 0), ("$rock1", 1)]);
+    assert_eq!(instances[1].transform.w.truncate(), cgmath::Vector3::new(2.0, 0.0, 0.0));
+    assert_eq!(instances[1].material_refs[0].material_ref, "$bark");
+    
+    // The baked models are still there, one per node
+    assert_eq!(model.get_vertex(), vec![vec![[1.0, 1.0, 0.0]], vec![[2.0, 1.0, 0.0]], vec![[0.0, 0.0, 0.0]]]);
+    
+    // Nodes that don't refer to an object aren't instances of unnamed ones
+    let source = "
+      GeometryNode $empty {Translation {float[3] {{1, 0, 0}}}}
+      GeometryObject {Mesh {VertexArray (attrib = \"position\") {float[3] {{0, 1, 0}}}}}";
+    let model = OpengexPaser::from_str(source).unwrap();
+    assert_eq!(model.get_meshes().len(), 1);
+    assert!(model.get_instances().is_empty());
+    assert!(model.get_vertex().is_empty());
+  }
+  
+  #[test]
+  fn references_resolve_to_the_right_kind_of_structure() {
+    let source = "BoneNode $root {BoneNode %tip {Translation {float[3] {{0, 1, 0}}}}}
+    GeometryNode {ObjectRef {ref {$mesh}} MaterialRef {ref {$paint}}}
+    GeometryObject $mesh {
+      Mesh {
+        VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}}}
+        Skin {
+          Skeleton {
+            BoneRefArray {ref {$root%tip}}
+            Transform {float[16] {{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1}}}
+          }
+          BoneCountArray {unsigned_int16 {1}}
+          BoneIndexArray {unsigned_int8 {0}}
+          BoneWeightArray {float {1}}
+        }
+      }
+    }
+    Material $paint {}";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let bone_ref = &model.get_skins()[0].unwrap().skeleton.bone_refs[0];
+    assert_eq!(bone_ref, "$root%tip");
+    assert_eq!(model.get_scene().find(bone_ref).unwrap().local_transform.w.y, 1.0);
+    assert_eq!(model.get_scene().nodes[1].geometry().unwrap().material_refs[0].material_ref, "$paint");
+    
+    let wrong_kinds = [
+      "GeometryNode {ObjectRef {ref {$m}}} Material $m {}",
+      "GeometryNode {ObjectRef {ref {$g}} MaterialRef {ref {$g}}} GeometryObject $g {}",
+      "LightNode {ObjectRef {ref {$g}}} GeometryObject $g {}",
+    ];
+    for source in wrong_kinds.iter() {
+      match OpengexPaser::from_str(source) {
+        Err(ParseError::TypeMismatch { .. }) => {},
+        _ => panic!("expected a reference to the wrong kind of structure in {}", source),
+      }
+    }
+  }
+  
+  #[test]
+  fn short_data_is_an_error() {
+    let skin = "BoneNode $root {}
+      GeometryNode {ObjectRef {ref {$mesh}}}
+      GeometryObject $mesh {
+        Mesh {
+          VertexArray (attrib = \"position\") {float[3] {{0, 0, 0}}}
+          Skin {
+            Transform {float[16] {{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1}}}
+            Skeleton {BoneRefArray {ref {$root}} Transform {float[16] {{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1}}}}
+            BoneCountArray {unsigned_int16 {1}}
+            BoneIndexArray {unsigned_int8 {0}}
+            BoneWeightArray {float {1}}
+          }
+        }
+      }";
+    assert!(OpengexPaser::from_str(skin).is_ok());
+    let empty_skin = skin.replacen("{{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1}}", "{}", 1);
+    let empty_skeleton = skin.replace("Transform {float[16] {{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1}}}}", "Transform {float[16] {}}}");
+    
+    let sources = [
+      "Node {Transform {float[16] {}}}",
+      "Material $m {Color (attrib = \"diffuse\") {float[3] {}}}",
+      "Metric (key = \"time\") {float {}}",
+      &empty_skin,
+      &empty_skeleton,
+    ];
+    for source in sources.iter() {
+      match OpengexPaser::from_str(source) {
+        Err(ParseError::InvalidValue { .. }) => {},
+        _ => panic!("expected short data in {}", source),
+      }
+    }
+  }
+  
+  #[test]
+  fn empty_transforms_are_errors() {
+    let sources = [
+      "Node {Translation {float[3] {}}}",
+      "Node {Translation {float[3] {{1, 2, 3}, {4, 5, 6}}}}",
+      "Node {Translation (kind = \"x\") {float {}}}",
+      "Node {Rotation {float[4] {}}}",
+      "Node {Rotation (kind = \"quaternion\") {float[4] {}}}",
+      "Node {Scale {float[3] {}}}",
+    ];
+    for source in sources.iter() {
+      match OpengexPaser::from_str(source) {
+        Err(ParseError::InvalidValue { message, .. }) => assert!(message.starts_with("Expected 1 float"), "{}", message),
+        _ => panic!("expected an error for {}", source),
+      }
+    }
+    assert!(OpengexPaser::from_str("Node {Rotation {float[4] {{0, 0, 0, 1}}}}").is_ok());
+  }
+  
+  #[test]
+  fn bezier_controls_default_to_their_keys() {
+    let source = "Node {
+      Translation %xpos (kind = \"x\") {float {0}}
+      Animation {
+        Track (target = %xpos) {
+          Time (curve = \"bezier\") {Key {float {0, 1}}}
+          Value (curve = \"bezier\") {Key {float {5, 5}}}
+        }
+        Track (target = %xpos) {
+          Time (curve = \"bezier\") {Key {float {0, 1}}}
+          Value (curve = \"bezier\") {Key {float {0, 4}}}
+        }
+      }
+    }";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let tracks = &model.get_scene().nodes[0].animations[0].tracks;
+    assert_eq!(tracks[0].sample(0.5), vec![5.0]);
+    // Without controls the curve is a straight line
+    assert!((tracks[1].sample(0.25)[0] - 1.0).abs() < 1e-4);
+  }
+  
+  #[test]
+  fn tcb_tangents_follow_key_spacing() {
+    let source = "Node {
+      Translation %xpos (kind = \"x\") {float {0}}
+      Animation {
+        Track (target = %xpos) {
+          Time {Key {float {0, 1, 4, 5}}}
+          Value (curve = \"tcb\") {
+            Key {float {0, 1, 4, 5}}
+            Key (kind = \"tension\") {float {0, 0, 0, 0}}
+            Key (kind = \"continuity\") {float {0, 0, 0, 0}}
+            Key (kind = \"bias\") {float {0, 0, 0, 0}}
+          }
+        }
+      }
+    }";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let track = &model.get_scene().nodes[0].animations[0].tracks[0];
+    // Values that rise with time stay on that line between unevenly spaced
+    // keys, rather than lagging behind it
+    for &time in &[1.5, 2.0, 3.0, 3.5] {
+      assert!((track.sample(time)[0] - time).abs() < 1e-5, "{} at {}", track.sample(time)[0], time);
+    }
+  }
+  
+  #[test]
+  fn resolves_names_across_the_file() {
+    // Targets can be written as full paths, and are named by the structure they resolve to
+    let source = "Node $node1 {
+      Translation %xpos (kind = \"x\") {float {0}}
+      Animation {Track (target = $node1%xpos) {Time {Key {float {0, 1}}} Value {Key {float {0, 1}}}}}
+    }";
+    let model = OpengexPaser::from_str(source).unwrap();
+    let track = &model.get_animations()[0].1.tracks[0];
+    assert_eq!((track.target_ref.as_str(), track.target), ("%xpos", TargetType::Xpos));
+    
+    match OpengexPaser::from_str("Node $node1 {}\nMaterial $node1 {}") {
+      Err(ParseError::DuplicateName { location, name }) => assert_eq!((name.as_str(), location.line), ("$node1", 2)),
+      _ => panic!("expected a duplicate name"),
+    }
+    // Resolving to something that isn't in the node
+    match OpengexPaser::from_str("Node $node1 {Name {string {\"a\"}} Animation {Track (target = $node1) {}}}") {
+      Err(ParseError::InvalidValue { .. }) => {},
+      _ => panic!("expected an invalid target"),
+    }
+  }
+  
+  #[test]
+  fn unbaked_models_stay_in_object_space() {
+    let source = "
+      Node $arm {
+        Translation {float[3] {{0, 2, 0}}}
+        GeometryNode $hand {
+          ObjectRef {ref {$box}}
+          Translation {float[3] {{1, 0, 0}}}
+          Scale (object = true) {float[3] {{2, 2, 2}}}
+        }
+      }
+      GeometryObject $box {
+        Mesh {
+          VertexArray (attrib = \"position\") {float[3] {{1, 1, 1}}}
+          VertexArray (attrib = \"normal\") {float[3] {{0, 0, 2}}}
+        }
+      }";
+    
+    let options = ParseOptions {
+      bake: false,
+      ..ParseOptions::new()
+    };
+    let model = OpengexPaser::from_str_with_options(source, &options).unwrap();
+    assert_eq!(model.get_vertex(), vec![vec![[1.0, 1.0, 1.0]]]);
+    assert_eq!(model.get_normal(), vec![vec![[0.0, 0.0, 2.0]]]);
+    
+    let instance = &model.get_instances()[0];
+    assert_eq!(instance.local_transform.w.truncate(), cgmath::Vector3::new(1.0, 0.0, 0.0));
+    assert_eq!(instance.world_transform.w.truncate(), cgmath::Vector3::new(1.0, 2.0, 0.0));
+    assert_eq!(instance.transform.x.x, 2.0);
+    
+    // Baking is the same as applying the instance's transform
+    let baked = OpengexPaser::from_str(source).unwrap();
+    assert_eq!(baked.get_vertex(), vec![vec![[3.0, 4.0, 2.0]]]);
+    assert_eq!(baked.get_normal(), vec![vec![[0.0, 0.0, 1.0]]]);
+  }
 }
-
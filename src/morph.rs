@@ -0,0 +1,28 @@
+use scalar::Scalar;
+
+// A morph target of a mesh, stored as offsets from the target it's based on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Morph {
+  pub index: u32,
+  // The morph target this one is relative to, None when it's relative to
+  // the mesh itself, morph target 0
+  pub base: Option<u32>,
+  // The contents of the Morph structure's Name structure
+  pub name: String,
+  // One offset per vertex, empty when the morph target doesn't change
+  // that attribute
+  pub position_deltas: Vec<[Scalar; 3]>,
+  pub normal_deltas: Vec<[Scalar; 3]>,
+}
+
+impl Morph {
+  pub fn new(index: u32) -> Morph {
+    Morph {
+      index,
+      base: None,
+      name: "".to_string(),
+      position_deltas: Vec::new(),
+      normal_deltas: Vec::new(),
+    }
+  }
+}
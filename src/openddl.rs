@@ -0,0 +1,1886 @@
+use std::fmt;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataType {
+  Bool,
+  Int8,
+  Int16,
+  Int32,
+  Int64,
+  UnsignedInt8,
+  UnsignedInt16,
+  UnsignedInt32,
+  UnsignedInt64,
+  Half,
+  Float,
+  Double,
+  String,
+  Ref,
+  Type,
+}
+
+impl fmt::Display for DataType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let identifier = match *self {
+      DataType::Bool => "bool",
+      DataType::Int8 => "int8",
+      DataType::Int16 => "int16",
+      DataType::Int32 => "int32",
+      DataType::Int64 => "int64",
+      DataType::UnsignedInt8 => "unsigned_int8",
+      DataType::UnsignedInt16 => "unsigned_int16",
+      DataType::UnsignedInt32 => "unsigned_int32",
+      DataType::UnsignedInt64 => "unsigned_int64",
+      DataType::Half => "half",
+      DataType::Float => "float",
+      DataType::Double => "double",
+      DataType::String => "string",
+      DataType::Ref => "ref",
+      DataType::Type => "type",
+    };
+    write!(f, "{}", identifier)
+  }
+}
+
+impl DataType {
+  pub fn from_identifier(identifier: &str) -> Option<DataType> {
+    let data_type = match identifier {
+      "bool" | "b" => DataType::Bool,
+      "int8" | "i8" => DataType::Int8,
+      "int16" | "i16" => DataType::Int16,
+      "int32" | "i32" => DataType::Int32,
+      "int64" | "i64" => DataType::Int64,
+      "unsigned_int8" | "uint8" | "u8" => DataType::UnsignedInt8,
+      "unsigned_int16" | "uint16" | "u16" => DataType::UnsignedInt16,
+      "unsigned_int32" | "uint32" | "u32" => DataType::UnsignedInt32,
+      "unsigned_int64" | "uint64" | "u64" => DataType::UnsignedInt64,
+      "half" | "float16" | "h" | "f16" => DataType::Half,
+      "float" | "float32" | "f" | "f32" => DataType::Float,
+      "double" | "float64" | "d" | "f64" => DataType::Double,
+      "string" | "s" => DataType::String,
+      "ref" | "r" => DataType::Ref,
+      "type" | "t" => DataType::Type,
+      _ => return None,
+    };
+    Some(data_type)
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Name {
+  Global(String),
+  Local(String),
+}
+
+// Written the way it is in a file, like $node1 or %xpos
+impl fmt::Display for Name {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Name::Global(ref name) => write!(f, "${}", name),
+      Name::Local(ref name) => write!(f, "%{}", name),
+    }
+  }
+}
+
+// An empty list of names is the null reference.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+  pub names: Vec<Name>,
+}
+
+impl Reference {
+  pub fn is_null(&self) -> bool {
+    self.names.is_empty()
+  }
+}
+
+impl fmt::Display for Reference {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_null() {
+      return write!(f, "null");
+    }
+    for name in &self.names {
+      write!(f, "{}", name)?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+  Bool(bool),
+  Integer(i64),
+  Float(f64),
+  String(String),
+  Ref(Reference),
+  Type(DataType),
+}
+
+impl Literal {
+  pub fn as_str(&self) -> Option<&str> {
+    match *self {
+      Literal::String(ref string) => Some(string),
+      _ => None,
+    }
+  }
+
+  pub fn as_i64(&self) -> Option<i64> {
+    match *self {
+      Literal::Integer(int) => Some(int),
+      _ => None,
+    }
+  }
+
+  pub fn as_f32(&self) -> Option<f32> {
+    match *self {
+      Literal::Integer(int) => Some(int as f32),
+      Literal::Float(float) => Some(float as f32),
+      _ => None,
+    }
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match *self {
+      Literal::Bool(b) => Some(b),
+      _ => None,
+    }
+  }
+
+  pub fn as_f64(&self) -> Option<f64> {
+    match *self {
+      Literal::Integer(int) => Some(int as f64),
+      Literal::Float(float) => Some(float),
+      _ => None,
+    }
+  }
+
+  pub fn as_reference(&self) -> Option<&Reference> {
+    match *self {
+      Literal::Ref(ref reference) => Some(reference),
+      _ => None,
+    }
+  }
+
+  pub fn as_type(&self) -> Option<DataType> {
+    match *self {
+      Literal::Type(data_type) => Some(data_type),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Property {
+  pub key: String,
+  pub value: Literal,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Data {
+  Bool(Vec<bool>),
+  Int8(Vec<i8>),
+  Int16(Vec<i16>),
+  Int32(Vec<i32>),
+  Int64(Vec<i64>),
+  UnsignedInt8(Vec<u8>),
+  UnsignedInt16(Vec<u16>),
+  UnsignedInt32(Vec<u32>),
+  UnsignedInt64(Vec<u64>),
+  Half(Vec<f32>),
+  Float(Vec<f32>),
+  Double(Vec<f64>),
+  String(Vec<String>),
+  Ref(Vec<Reference>),
+  Type(Vec<DataType>),
+}
+
+// array_size is 0 for a flat list of values, otherwise the values are
+// stored flattened with array_size elements per subarray.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataList {
+  pub array_size: usize,
+  pub data: Data,
+}
+
+impl DataList {
+  pub fn data_type(&self) -> DataType {
+    match self.data {
+      Data::Bool(_) => DataType::Bool,
+      Data::Int8(_) => DataType::Int8,
+      Data::Int16(_) => DataType::Int16,
+      Data::Int32(_) => DataType::Int32,
+      Data::Int64(_) => DataType::Int64,
+      Data::UnsignedInt8(_) => DataType::UnsignedInt8,
+      Data::UnsignedInt16(_) => DataType::UnsignedInt16,
+      Data::UnsignedInt32(_) => DataType::UnsignedInt32,
+      Data::UnsignedInt64(_) => DataType::UnsignedInt64,
+      Data::Half(_) => DataType::Half,
+      Data::Float(_) => DataType::Float,
+      Data::Double(_) => DataType::Double,
+      Data::String(_) => DataType::String,
+      Data::Ref(_) => DataType::Ref,
+      Data::Type(_) => DataType::Type,
+    }
+  }
+
+  // The type as it is written in a file, like float[3]
+  pub fn type_name(&self) -> String {
+    if self.array_size == 0 {
+      self.data_type().to_string()
+    } else {
+      format!("{}[{}]", self.data_type(), self.array_size)
+    }
+  }
+
+  // Any floating point type as f32, only copying if it was a double
+  pub fn as_f32s(&self) -> Option<Cow<'_, [f32]>> {
+    match self.data {
+      Data::Half(ref floats) | Data::Float(ref floats) => Some(Cow::Borrowed(floats)),
+      Data::Double(ref doubles) => Some(Cow::Owned(doubles.iter().map(|&double| double as f32).collect())),
+      _ => None,
+    }
+  }
+
+  // Any floating point type as f64, only copying if it wasn't a double
+  pub fn as_f64s(&self) -> Option<Cow<'_, [f64]>> {
+    match self.data {
+      Data::Half(ref floats) | Data::Float(ref floats) => Some(Cow::Owned(floats.iter().map(|&float| f64::from(float)).collect())),
+      Data::Double(ref doubles) => Some(Cow::Borrowed(doubles)),
+      _ => None,
+    }
+  }
+
+  // Any unsigned integer type widened to u32, None if a value doesn't fit
+  pub fn unsigned_integers(&self) -> Option<Vec<u32>> {
+    match self.data {
+      Data::UnsignedInt8(ref ints) => Some(ints.iter().map(|&int| int as u32).collect()),
+      Data::UnsignedInt16(ref ints) => Some(ints.iter().map(|&int| int as u32).collect()),
+      Data::UnsignedInt32(ref ints) => Some(ints.clone()),
+      Data::UnsignedInt64(ref ints) => ints.iter().map(|&int| if int <= u32::MAX as u64 { Some(int as u32) } else { None }).collect(),
+      _ => None,
+    }
+  }
+
+  // The number of values, counting each subarray as one value
+  pub fn len(&self) -> usize {
+    let values = match self.data {
+      Data::Bool(ref values) => values.len(),
+      Data::Int8(ref values) => values.len(),
+      Data::Int16(ref values) => values.len(),
+      Data::Int32(ref values) => values.len(),
+      Data::Int64(ref values) => values.len(),
+      Data::UnsignedInt8(ref values) => values.len(),
+      Data::UnsignedInt16(ref values) => values.len(),
+      Data::UnsignedInt32(ref values) => values.len(),
+      Data::UnsignedInt64(ref values) => values.len(),
+      Data::Half(ref values) | Data::Float(ref values) => values.len(),
+      Data::Double(ref values) => values.len(),
+      Data::String(ref values) => values.len(),
+      Data::Ref(ref values) => values.len(),
+      Data::Type(ref values) => values.len(),
+    };
+    values/self.array_size.max(1)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+// The values of one type, None if the data is any other type
+macro_rules! data_accessor {
+  ($name:ident, $variant:ident, $t:ty) => {
+    impl DataList {
+      pub fn $name(&self) -> Option<&[$t]> {
+        match self.data {
+          Data::$variant(ref values) => Some(values),
+          _ => None,
+        }
+      }
+    }
+  };
+}
+
+data_accessor!(bools, Bool, bool);
+data_accessor!(int8s, Int8, i8);
+data_accessor!(int16s, Int16, i16);
+data_accessor!(int32s, Int32, i32);
+data_accessor!(int64s, Int64, i64);
+data_accessor!(unsigned_int8s, UnsignedInt8, u8);
+data_accessor!(unsigned_int16s, UnsignedInt16, u16);
+data_accessor!(unsigned_int32s, UnsignedInt32, u32);
+data_accessor!(unsigned_int64s, UnsignedInt64, u64);
+data_accessor!(halfs, Half, f32);
+data_accessor!(floats, Float, f32);
+data_accessor!(doubles, Double, f64);
+data_accessor!(strings, String, String);
+data_accessor!(references, Ref, Reference);
+data_accessor!(types, Type, DataType);
+
+// A primitive structure has data and no children, a derived structure has
+// children and no data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Structure {
+  pub identifier: String,
+  pub name: Option<Name>,
+  pub properties: Vec<Property>,
+  pub data: Option<DataList>,
+  pub children: Vec<Structure>,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Structure {
+  pub fn property(&self, key: &str) -> Option<&Literal> {
+    self.properties.iter().find(|property| property.key == key).map(|property| &property.value)
+  }
+
+  pub fn child(&self, identifier: &str) -> Option<&Structure> {
+    self.children.iter().find(|child| child.identifier == identifier)
+  }
+
+  // The first primitive substructure, which is how OpenGEX stores the
+  // contents of structures like Name, Transform and Key.
+  pub fn primitive_structure(&self) -> Option<&Structure> {
+    self.children.iter().find(|child| child.data.is_some())
+  }
+
+  pub fn primitive(&self) -> Option<&DataList> {
+    self.primitive_structure().and_then(|child| child.data.as_ref())
+  }
+
+  pub fn is_primitive(&self) -> bool {
+    self.data.is_some()
+  }
+
+  // Every direct substructure with the identifier
+  pub fn children_with<'a>(&'a self, identifier: &'a str) -> impl Iterator<Item = &'a Structure> + 'a {
+    self.children.iter().filter(move |child| child.identifier == identifier)
+  }
+
+  // The direct substructure with a local name, like %xpos
+  pub fn local(&self, name: &str) -> Option<&Structure> {
+    local_child(&self.children, name.trim_start_matches('%'))
+  }
+
+  // This structure and all of its substructures, parents first
+  pub fn iter(&self) -> StructureIter<'_> {
+    StructureIter {
+      stack: vec![self],
+    }
+  }
+}
+
+pub struct StructureIter<'a> {
+  stack: Vec<&'a Structure>,
+}
+
+impl<'a> Iterator for StructureIter<'a> {
+  type Item = &'a Structure;
+
+  fn next(&mut self) -> Option<&'a Structure> {
+    let structure = self.stack.pop()?;
+    self.stack.extend(structure.children.iter().rev());
+    Some(structure)
+  }
+}
+
+// The top level structures of a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+  pub structures: Vec<Structure>,
+}
+
+impl Document {
+  pub fn new() -> Document {
+    Document {
+      structures: Vec::new(),
+    }
+  }
+
+  // Every structure in the document, parents first
+  pub fn iter(&self) -> StructureIter<'_> {
+    StructureIter {
+      stack: self.structures.iter().rev().collect(),
+    }
+  }
+
+  // Every structure in the document with the identifier, at any depth
+  pub fn structures_with<'a>(&'a self, identifier: &'a str) -> impl Iterator<Item = &'a Structure> + 'a {
+    self.iter().filter(move |structure| structure.identifier == identifier)
+  }
+
+  // The structure with a global name, like $node1
+  pub fn find(&self, name: &str) -> Option<&Structure> {
+    let name = name.trim_start_matches('$');
+    self.iter().find(|structure| match structure.name {
+      Some(Name::Global(ref global)) => global == name,
+      _ => false,
+    })
+  }
+}
+
+impl Default for Document {
+  fn default() -> Document {
+    Document::new()
+  }
+}
+
+impl FromStr for Document {
+  type Err = SyntaxError;
+
+  fn from_str(source: &str) -> Result<Document, SyntaxError> {
+    Ok(Document { structures: parse(source)? })
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxError {
+  pub message: String,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl fmt::Display for SyntaxError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TokenKind<'a> {
+  Identifier(&'a str),
+  GlobalName(&'a str),
+  LocalName(&'a str),
+  Number(&'a str),
+  Str(&'a str),
+  Char(&'a str),
+  OpenBrace,
+  CloseBrace,
+  OpenParen,
+  CloseParen,
+  OpenBracket,
+  CloseBracket,
+  Comma,
+  Equals,
+  Eof,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+  kind: TokenKind<'a>,
+  line: usize,
+  column: usize,
+}
+
+struct Lexer<'a> {
+  source: &'a str,
+  bytes: &'a [u8],
+  position: usize,
+  line: usize,
+  line_start: usize,
+}
+
+// Where the lexer is in the source, along with the line it's on
+#[cfg(feature = "parallel")]
+#[derive(Clone, Copy, Debug)]
+struct Position {
+  position: usize,
+  line: usize,
+  line_start: usize,
+}
+
+fn is_identifier_start(c: u8) -> bool {
+  c.is_ascii_alphabetic() || c == b'_'
+}
+
+fn is_identifier_char(c: u8) -> bool {
+  c.is_ascii_alphanumeric() || c == b'_'
+}
+
+impl<'a> Lexer<'a> {
+  fn new(source: &'a str) -> Lexer<'a> {
+    Lexer {
+      source,
+      bytes: source.as_bytes(),
+      position: 0,
+      line: 1,
+      line_start: 0,
+    }
+  }
+
+  // A lexer for part of the source, up to end, starting partway through
+  #[cfg(feature = "parallel")]
+  fn at(source: &'a str, end: usize, start: Position) -> Lexer<'a> {
+    Lexer {
+      source: &source[..end],
+      bytes: &source.as_bytes()[..end],
+      position: start.position,
+      line: start.line,
+      line_start: start.line_start,
+    }
+  }
+
+  #[cfg(feature = "parallel")]
+  fn position(&self) -> Position {
+    Position {
+      position: self.position,
+      line: self.line,
+      line_start: self.line_start,
+    }
+  }
+
+  fn peek_byte(&self, offset: usize) -> Option<u8> {
+    self.bytes.get(self.position + offset).cloned()
+  }
+
+  fn column(&self) -> usize {
+    self.position - self.line_start + 1
+  }
+
+  fn error(&self, message: String) -> SyntaxError {
+    SyntaxError {
+      message,
+      line: self.line,
+      column: self.column(),
+    }
+  }
+
+  // Any control character or space is whitespace, and comments count as
+  // whitespace too
+  fn skip_whitespace(&mut self) -> Result<(), SyntaxError> {
+    while let Some(c) = self.peek_byte(0) {
+      match c {
+        b'\n' => {
+          self.position += 1;
+          self.line += 1;
+          self.line_start = self.position;
+        },
+        b'/' if self.peek_byte(1) == Some(b'/') => {
+          while let Some(c) = self.peek_byte(0) {
+            if c == b'\n' {
+              break;
+            }
+            self.position += 1;
+          }
+        },
+        b'/' if self.peek_byte(1) == Some(b'*') => {
+          let (line, column) = (self.line, self.column());
+          self.position += 2;
+          loop {
+            match self.peek_byte(0) {
+              Some(b'*') if self.peek_byte(1) == Some(b'/') => {
+                self.position += 2;
+                break;
+              },
+              Some(b'\n') => {
+                self.position += 1;
+                self.line += 1;
+                self.line_start = self.position;
+              },
+              Some(_) => self.position += 1,
+              None => {
+                return Err(SyntaxError {
+                  message: "Unterminated block comment".to_string(),
+                  line,
+                  column,
+                });
+              },
+            }
+          }
+        },
+        1..=32 => {
+          self.position += 1;
+        },
+        _ => break,
+      }
+    }
+    Ok(())
+  }
+
+  fn take_while<F: Fn(u8) -> bool>(&mut self, f: F) -> &'a str {
+    let start = self.position;
+    while let Some(c) = self.peek_byte(0) {
+      if !f(c) {
+        break;
+      }
+      self.position += 1;
+    }
+    &self.source[start..self.position]
+  }
+
+  fn name(&mut self) -> Result<&'a str, SyntaxError> {
+    self.position += 1;
+    match self.peek_byte(0) {
+      Some(c) if is_identifier_start(c) => Ok(self.take_while(is_identifier_char)),
+      _ => Err(self.error("Expected an identifier after name prefix".to_string())),
+    }
+  }
+
+  fn number(&mut self) -> &'a str {
+    let start = self.position;
+    if let Some(b'-') | Some(b'+') = self.peek_byte(0) {
+      self.position += 1;
+    }
+    while let Some(c) = self.peek_byte(0) {
+      let exponent_sign = (c == b'-' || c == b'+') && (self.bytes[self.position - 1] | 0x20) == b'e';
+      if !(c.is_ascii_alphanumeric() || c == b'.' || c == b'_' || exponent_sign) {
+        break;
+      }
+      self.position += 1;
+    }
+    &self.source[start..self.position]
+  }
+
+  // The raw contents of a string or character literal, escapes are
+  // decoded by the parser
+  fn quoted(&mut self, quote: u8, what: &str) -> Result<&'a str, SyntaxError> {
+    let (line, column) = (self.line, self.column());
+    self.position += 1;
+    let start = self.position;
+    loop {
+      match self.peek_byte(0) {
+        Some(c) if c == quote => break,
+        Some(b'\\') if self.peek_byte(1).is_some() && self.peek_byte(1) != Some(b'\n') => self.position += 2,
+        Some(b'\n') | None => {
+          return Err(SyntaxError {
+            message: format!("Unterminated {} literal", what),
+            line,
+            column,
+          });
+        },
+        Some(_) => self.position += 1,
+      }
+    }
+    let string = &self.source[start..self.position];
+    self.position += 1;
+    Ok(string)
+  }
+
+  fn next_token(&mut self) -> Result<Token<'a>, SyntaxError> {
+    self.skip_whitespace()?;
+    let (line, column) = (self.line, self.column());
+
+    let c = match self.peek_byte(0) {
+      Some(c) => c,
+      None => return Ok(Token { kind: TokenKind::Eof, line, column }),
+    };
+
+    let kind = match c {
+      b'{' => { self.position += 1; TokenKind::OpenBrace },
+      b'}' => { self.position += 1; TokenKind::CloseBrace },
+      b'(' => { self.position += 1; TokenKind::OpenParen },
+      b')' => { self.position += 1; TokenKind::CloseParen },
+      b'[' => { self.position += 1; TokenKind::OpenBracket },
+      b']' => { self.position += 1; TokenKind::CloseBracket },
+      b',' => { self.position += 1; TokenKind::Comma },
+      b'=' => { self.position += 1; TokenKind::Equals },
+      b'$' => TokenKind::GlobalName(self.name()?),
+      b'%' => TokenKind::LocalName(self.name()?),
+      b'"' => TokenKind::Str(self.quoted(b'"', "string")?),
+      b'\'' => TokenKind::Char(self.quoted(b'\'', "character")?),
+      _ if is_identifier_start(c) => TokenKind::Identifier(self.take_while(is_identifier_char)),
+      _ if c.is_ascii_digit() || c == b'-' || c == b'+' || c == b'.' => TokenKind::Number(self.number()),
+      _ => {
+        let c = self.source[self.position..].chars().next().unwrap_or('?');
+        return Err(self.error(format!("Unexpected character {:?}", c)));
+      },
+    };
+
+    Ok(Token { kind, line, column })
+  }
+}
+
+fn hex_escape<I: Iterator<Item = char>>(chars: &mut I, digits: usize) -> Option<char> {
+  let mut value = 0;
+  for _ in 0..digits {
+    value = value*16 + chars.next()?.to_digit(16)?;
+  }
+  std::char::from_u32(value)
+}
+
+// None if the string has an invalid escape sequence
+fn unescape(raw: &str) -> Option<String> {
+  let mut string = String::with_capacity(raw.len());
+  let mut chars = raw.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      string.push(c);
+      continue;
+    }
+    let c = match chars.next()? {
+      '"' => '"',
+      '\'' => '\'',
+      '?' => '?',
+      '\\' => '\\',
+      'a' => '\u{7}',
+      'b' => '\u{8}',
+      'f' => '\u{c}',
+      'n' => '\n',
+      'r' => '\r',
+      't' => '\t',
+      'v' => '\u{b}',
+      'x' => hex_escape(&mut chars, 2)?,
+      'u' => hex_escape(&mut chars, 4)?,
+      'U' => hex_escape(&mut chars, 6)?,
+      _ => return None,
+    };
+    string.push(c);
+  }
+  Some(string)
+}
+
+// The sign and magnitude of a decimal, hex (0x), octal (0o) or binary (0b)
+// integer literal. Digits can be separated by single underscores.
+fn integer_literal(raw: &str) -> Option<(bool, u64)> {
+  let (negative, digits) = match raw.as_bytes().first() {
+    Some(b'-') => (true, &raw[1..]),
+    Some(b'+') => (false, &raw[1..]),
+    _ => (false, raw),
+  };
+  let (radix, digits) = match digits.get(..2) {
+    Some("0x") | Some("0X") => (16, &digits[2..]),
+    Some("0o") | Some("0O") => (8, &digits[2..]),
+    Some("0b") | Some("0B") => (2, &digits[2..]),
+    _ => (10, digits),
+  };
+  if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+    return None;
+  }
+
+  let mut value: u64 = 0;
+  for c in digits.chars().filter(|&c| c != '_') {
+    let digit = u64::from(c.to_digit(radix)?);
+    value = value.checked_mul(u64::from(radix))?.checked_add(digit)?;
+  }
+  Some((negative, value))
+}
+
+fn is_bit_pattern(raw: &str) -> bool {
+  let digits = raw.trim_start_matches(['-', '+']).as_bytes();
+  digits.len() > 2 && digits[0] == b'0' && matches!(digits[1], b'x' | b'X' | b'o' | b'O' | b'b' | b'B')
+}
+
+fn half_to_f32(bits: u16) -> f32 {
+  let sign = u32::from(bits >> 15) << 31;
+  let exponent = u32::from((bits >> 10) & 0x1f);
+  let mantissa = u32::from(bits & 0x3ff);
+  let bits = match exponent {
+    0 => {
+      // Zero and subnormals, which are normal numbers as f32
+      let value = mantissa as f32/16_777_216.0;
+      return if sign == 0 { value } else { -value };
+    },
+    0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+    _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+  };
+  f32::from_bits(bits)
+}
+
+// A decimal float, or the bits of a float of the given size written as a
+// hex, octal or binary literal
+fn float_literal(raw: &str, bits: u32) -> Option<f64> {
+  if is_bit_pattern(raw) {
+    let (negative, pattern) = integer_literal(raw)?;
+    if bits < 64 && pattern >> bits != 0 {
+      return None;
+    }
+    let value = match bits {
+      16 => f64::from(half_to_f32(pattern as u16)),
+      32 => f64::from(f32::from_bits(pattern as u32)),
+      _ => f64::from_bits(pattern),
+    };
+    return Some(if negative { -value } else { value });
+  }
+
+  decimal_literal(raw)
+}
+
+// Decimal floats are parsed straight to f32 so they're only rounded once
+fn float32_literal(raw: &str) -> Option<f32> {
+  if is_bit_pattern(raw) {
+    float_literal(raw, 32).map(|float| float as f32)
+  } else {
+    decimal_literal(raw)
+  }
+}
+
+// The digits are only copied when they're separated by underscores
+fn decimal_literal<T: FromStr>(raw: &str) -> Option<T> {
+  let valid = raw.bytes().all(|c| c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E' | b'+' | b'-' | b'_'));
+  if !valid || raw.contains("__") {
+    return None;
+  }
+  if raw.contains('_') {
+    raw.replace('_', "").parse().ok()
+  } else {
+    raw.parse().ok()
+  }
+}
+
+fn error_at(token: Token, message: String) -> SyntaxError {
+  SyntaxError {
+    message,
+    line: token.line,
+    column: token.column,
+  }
+}
+
+// Integers can be written as numbers or as character literals, whose
+// ASCII characters are the bytes of the value from most significant
+fn integer_value(data_type: DataType, token: Token) -> Result<i128, SyntaxError> {
+  match token.kind {
+    TokenKind::Number(number) => match integer_literal(number) {
+      Some((true, magnitude)) => Ok(-i128::from(magnitude)),
+      Some((false, magnitude)) => Ok(i128::from(magnitude)),
+      None => Err(error_at(token, format!("Invalid {:?} value {}", data_type, number))),
+    },
+    TokenKind::Char(raw) => {
+      let chars = match unescape(raw) {
+        Some(ref chars) if !chars.is_empty() && chars.len() <= 8 && chars.is_ascii() => chars.clone(),
+        _ => return Err(error_at(token, format!("Invalid character literal '{}'", raw))),
+      };
+      Ok(chars.bytes().fold(0, |value, byte| value << 8 | i128::from(byte)))
+    },
+    kind => Err(error_at(token, format!("Expected a {:?} value but found {:?}", data_type, kind))),
+  }
+}
+
+fn is_value(token: Token) -> bool {
+  matches!(token.kind, TokenKind::Number(_) | TokenKind::Char(_) | TokenKind::Identifier(_) |
+                       TokenKind::Str(_) | TokenKind::GlobalName(_) | TokenKind::LocalName(_))
+}
+
+// Decodes numbers as they're read, one value or subarray after another
+// until something other than a comma follows. Returns the token that ended
+// the list, which is the closing brace of well formed data.
+fn numbers_in<'a, T, F>(lexer: &mut Lexer<'a>, array_size: usize, decode: &F, values: &mut Vec<T>) -> Result<Token<'a>, SyntaxError>
+  where F: Fn(Token<'a>) -> Result<T, SyntaxError> {
+  let mut token = lexer.next_token()?;
+  loop {
+    if array_size == 0 {
+      if !is_value(token) {
+        return Ok(token);
+      }
+      values.push(decode(token)?);
+    } else {
+      if token.kind != TokenKind::OpenBrace {
+        return Ok(token);
+      }
+      let open = token;
+      let mut count = 0;
+      token = lexer.next_token()?;
+      while is_value(token) {
+        values.push(decode(token)?);
+        count += 1;
+        token = lexer.next_token()?;
+        if token.kind != TokenKind::Comma {
+          break;
+        }
+        token = lexer.next_token()?;
+      }
+      if count != array_size {
+        return Err(error_at(open, format!("Expected {} elements in subarray but found {}", array_size, count)));
+      }
+      if token.kind != TokenKind::CloseBrace {
+        return Err(error_at(token, format!("Expected '}}' but found {:?}", token.kind)));
+      }
+    }
+    token = lexer.next_token()?;
+    if token.kind != TokenKind::Comma {
+      return Ok(token);
+    }
+    token = lexer.next_token()?;
+  }
+}
+
+// Where each part of a long data list starts, splitting it after commas
+// that aren't inside a subarray, and where the list's closing brace is.
+// None when the list doesn't end, which is left to the lexer to report.
+#[cfg(feature = "parallel")]
+fn split_data(lexer: &Lexer) -> Option<(Vec<Position>, Position)> {
+  let bytes = lexer.bytes;
+  let mut at = lexer.position();
+  let mut parts = vec![at];
+  let mut depth = 0;
+  while let Some(&c) = bytes.get(at.position) {
+    match c {
+      b'\n' => at.line_start = at.position + 1,
+      b'{' => depth += 1,
+      b'}' if depth == 0 => return Some((parts, at)),
+      b'}' => depth -= 1,
+      b',' if depth == 0 && at.position + 1 - parts[parts.len() - 1].position >= PARALLEL_CHUNK => {
+        parts.push(Position { position: at.position + 1, ..at });
+      },
+      b'"' | b'\'' => {
+        at.position += 1;
+        while *bytes.get(at.position)? != c {
+          match bytes[at.position] {
+            b'\\' => at.position += 2,
+            b'\n' => return None,
+            _ => at.position += 1,
+          }
+        }
+      },
+      b'/' if bytes.get(at.position + 1) == Some(&b'/') => {
+        while bytes.get(at.position + 1).is_some_and(|&c| c != b'\n') {
+          at.position += 1;
+        }
+      },
+      b'/' if bytes.get(at.position + 1) == Some(&b'*') => {
+        at.position += 2;
+        while !(bytes.get(at.position)? == &b'*' && bytes.get(at.position + 1) == Some(&b'/')) {
+          if bytes[at.position] == b'\n' {
+            at.line += 1;
+            at.line_start = at.position + 1;
+          }
+          at.position += 1;
+        }
+        at.position += 1;
+      },
+      _ => {},
+    }
+    if c == b'\n' {
+      at.line += 1;
+    }
+    at.position += 1;
+  }
+  None
+}
+
+// Bytes of source per part of a data list decoded on rayon's thread pool
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK: usize = 128*1024;
+
+struct Parser<'a> {
+  lexer: Lexer<'a>,
+  current: Token<'a>,
+}
+
+impl<'a> Parser<'a> {
+  fn new(source: &'a str) -> Result<Parser<'a>, SyntaxError> {
+    let mut lexer = Lexer::new(source);
+    let current = lexer.next_token()?;
+    Ok(Parser { lexer, current })
+  }
+
+  fn advance(&mut self) -> Result<Token<'a>, SyntaxError> {
+    let token = self.current;
+    self.current = self.lexer.next_token()?;
+    Ok(token)
+  }
+
+  fn expect(&mut self, kind: TokenKind<'a>, what: &str) -> Result<Token<'a>, SyntaxError> {
+    if self.current.kind == kind {
+      self.advance()
+    } else {
+      let token = self.current;
+      Err(error_at(token, format!("Expected {} but found {:?}", what, token.kind)))
+    }
+  }
+
+  fn eat(&mut self, kind: TokenKind<'a>) -> Result<bool, SyntaxError> {
+    if self.current.kind == kind {
+      self.advance()?;
+      Ok(true)
+    } else {
+      Ok(false)
+    }
+  }
+
+  // Reads up to the next event, queueing the property, data and end events
+  // that immediately follow a structure's begin event
+  fn event(&mut self, open: &mut Vec<&'a str>, pending: &mut VecDeque<Event<'a>>) -> Result<Option<Event<'a>>, SyntaxError> {
+    match self.current.kind {
+      TokenKind::CloseBrace if !open.is_empty() => {
+        self.advance()?;
+        open.pop();
+        return Ok(Some(Event::StructureEnd));
+      },
+      TokenKind::Eof => {
+        return match open.last() {
+          Some(identifier) => {
+            let token = self.current;
+            Err(error_at(token, format!("Unexpected end of file inside {}", identifier)))
+          },
+          None => Ok(None),
+        };
+      },
+      _ => {},
+    }
+
+    let token = self.advance()?;
+    let identifier = match token.kind {
+      TokenKind::Identifier(identifier) => identifier,
+      kind => return Err(error_at(token, format!("Expected a structure identifier but found {:?}", kind))),
+    };
+
+    if let Some(data_type) = DataType::from_identifier(identifier) {
+      let (name, data) = self.primitive_structure(data_type)?;
+      pending.push_back(Event::Data(data));
+      pending.push_back(Event::StructureEnd);
+      return Ok(Some(Event::StructureBegin {
+        identifier,
+        name,
+        line: token.line,
+        column: token.column,
+      }));
+    }
+
+    let name = self.name()?;
+
+    if self.eat(TokenKind::OpenParen)? {
+      if self.current.kind != TokenKind::CloseParen {
+        loop {
+          pending.push_back(Event::Property(self.property()?));
+          if !self.eat(TokenKind::Comma)? {
+            break;
+          }
+        }
+      }
+      self.expect(TokenKind::CloseParen, "')'")?;
+    }
+
+    self.expect(TokenKind::OpenBrace, "'{'")?;
+    open.push(identifier);
+
+    Ok(Some(Event::StructureBegin {
+      identifier,
+      name,
+      line: token.line,
+      column: token.column,
+    }))
+  }
+
+  fn primitive_structure(&mut self, data_type: DataType) -> Result<(Option<Name>, DataList), SyntaxError> {
+    let mut array_size = 0;
+    if self.eat(TokenKind::OpenBracket)? {
+      let size = self.advance()?;
+      array_size = match size.kind {
+        TokenKind::Number(number) => match number.parse::<usize>() {
+          Ok(size) if size > 0 => size,
+          _ => return Err(error_at(size, format!("Invalid array size {}", number))),
+        },
+        kind => return Err(error_at(size, format!("Expected an array size but found {:?}", kind))),
+      };
+      self.expect(TokenKind::CloseBracket, "']'")?;
+    }
+
+    let name = self.name()?;
+
+    let data = self.data(data_type, array_size)?;
+    Ok((name, DataList { array_size, data }))
+  }
+
+  // Numbers are decoded straight from the source as they're scanned, the
+  // other types from the tokens that make up each value
+  fn data(&mut self, data_type: DataType, array_size: usize) -> Result<Data, SyntaxError> {
+    macro_rules! integers {
+      ($variant:ident, $t:ty) => {
+        Data::$variant(self.numbers(array_size, |token| {
+          let int = integer_value(data_type, token)?;
+          <$t>::try_from(int).map_err(|_| error_at(token, format!("{} is out of range for {:?}", int, data_type)))
+        })?)
+      };
+    }
+
+    macro_rules! floats {
+      ($variant:ident, $literal:expr) => {
+        Data::$variant(self.numbers(array_size, |token| match token.kind {
+          TokenKind::Number(number) => match $literal(number) {
+            Some(float) => Ok(float),
+            None => Err(error_at(token, format!("Invalid {:?} value {}", data_type, number))),
+          },
+          kind => Err(error_at(token, format!("Expected a {:?} value but found {:?}", data_type, kind))),
+        })?)
+      };
+    }
+
+    let data = match data_type {
+      DataType::Int8 => integers!(Int8, i8),
+      DataType::Int16 => integers!(Int16, i16),
+      DataType::Int32 => integers!(Int32, i32),
+      DataType::Int64 => integers!(Int64, i64),
+      DataType::UnsignedInt8 => integers!(UnsignedInt8, u8),
+      DataType::UnsignedInt16 => integers!(UnsignedInt16, u16),
+      DataType::UnsignedInt32 => integers!(UnsignedInt32, u32),
+      DataType::UnsignedInt64 => integers!(UnsignedInt64, u64),
+      DataType::Half => floats!(Half, |number| float_literal(number, 16).map(|float| float as f32)),
+      DataType::Float => floats!(Float, float32_literal),
+      DataType::Double => floats!(Double, |number| float_literal(number, 64)),
+      DataType::Bool => Data::Bool(self.values(array_size, |parser, value| match parser.literal(value)? {
+        Literal::Bool(b) => Ok(b),
+        _ => Err(error_at(value[0], "Expected a bool value".to_string())),
+      })?),
+      DataType::String => Data::String(self.values(array_size, |parser, value| parser.string(value))?),
+      DataType::Ref => Data::Ref(self.values(array_size, |parser, value| parser.reference(value))?),
+      DataType::Type => Data::Type(self.values(array_size, |parser, value| match parser.literal(value)? {
+        Literal::Type(data_type) => Ok(data_type),
+        _ => Err(error_at(value[0], "Expected a type value".to_string())),
+      })?),
+    };
+    Ok(data)
+  }
+
+  // Decodes a braced list of numbers without collecting their tokens. With
+  // the parallel feature long lists are split into parts that are decoded
+  // on rayon's thread pool.
+  fn numbers<T, F>(&mut self, array_size: usize, decode: F) -> Result<Vec<T>, SyntaxError>
+    where T: Send, F: Fn(Token<'a>) -> Result<T, SyntaxError> + Sync {
+    // The lexer is just past the opening brace while it's the current token
+    if self.current.kind != TokenKind::OpenBrace {
+      let token = self.current;
+      return Err(error_at(token, format!("Expected '{{' but found {:?}", token.kind)));
+    }
+
+    #[cfg(feature = "parallel")]
+    {
+      use rayon::prelude::*;
+
+      if let Some((parts, end)) = split_data(&self.lexer) {
+        if parts.len() > 1 {
+          let source = self.lexer.source;
+          let ends: Vec<usize> = parts[1..].iter().map(|part| part.position).chain(Some(end.position)).collect();
+          let decoded: Vec<Result<Vec<T>, SyntaxError>> = parts.par_iter().zip(ends).map(|(&start, end)| {
+            let mut lexer = Lexer::at(source, end, start);
+            let mut values = Vec::new();
+            let token = numbers_in(&mut lexer, array_size, &decode, &mut values)?;
+            if token.kind != TokenKind::Eof {
+              return Err(error_at(token, format!("Expected '}}' but found {:?}", token.kind)));
+            }
+            Ok(values)
+          }).collect();
+
+          let mut values = Vec::new();
+          for part in decoded {
+            values.extend(part?);
+          }
+          self.lexer = Lexer::at(source, source.len(), end);
+          let token = self.lexer.next_token()?;
+          self.end_data(token)?;
+          return Ok(values);
+        }
+      }
+    }
+
+    let mut values = Vec::new();
+    let token = numbers_in(&mut self.lexer, array_size, &decode, &mut values)?;
+    self.end_data(token)?;
+    Ok(values)
+  }
+
+  fn end_data(&mut self, token: Token<'a>) -> Result<(), SyntaxError> {
+    if token.kind != TokenKind::CloseBrace {
+      return Err(error_at(token, format!("Expected '}}' but found {:?}", token.kind)));
+    }
+    self.current = self.lexer.next_token()?;
+    Ok(())
+  }
+
+  // Decodes a braced list of values from the tokens that make up each one
+  fn values<T, F>(&mut self, array_size: usize, decode: F) -> Result<Vec<T>, SyntaxError>
+    where F: Fn(&Parser<'a>, &[Token<'a>]) -> Result<T, SyntaxError> {
+    self.expect(TokenKind::OpenBrace, "'{'")?;
+    let mut tokens = Vec::new();
+    let mut starts = Vec::new();
+    if array_size == 0 {
+      self.data_values(&mut tokens, &mut starts)?;
+    } else {
+      while self.current.kind == TokenKind::OpenBrace {
+        let open = self.advance()?;
+        let count = self.data_values(&mut tokens, &mut starts)?;
+        if count != array_size {
+          return Err(error_at(open, format!("Expected {} elements in subarray but found {}", array_size, count)));
+        }
+        self.expect(TokenKind::CloseBrace, "'}'")?;
+        if !self.eat(TokenKind::Comma)? {
+          break;
+        }
+      }
+    }
+    self.expect(TokenKind::CloseBrace, "'}'")?;
+
+    let mut values = Vec::with_capacity(starts.len());
+    for (i, &start) in starts.iter().enumerate() {
+      let end = starts.get(i + 1).cloned().unwrap_or(tokens.len());
+      values.push(decode(self, &tokens[start..end])?);
+    }
+    Ok(values)
+  }
+
+  fn name(&mut self) -> Result<Option<Name>, SyntaxError> {
+    let name = match self.current.kind {
+      TokenKind::GlobalName(name) => Name::Global(name.to_string()),
+      TokenKind::LocalName(name) => Name::Local(name.to_string()),
+      _ => return Ok(None),
+    };
+    self.advance()?;
+    Ok(Some(name))
+  }
+
+  // Collects the raw tokens of a comma separated list of literals into one
+  // flat list, along with where each value starts, and returns how many
+  // values were read. A reference counts as a single value made up of
+  // several name tokens.
+  fn data_values(&mut self, tokens: &mut Vec<Token<'a>>, starts: &mut Vec<usize>) -> Result<usize, SyntaxError> {
+    let count = starts.len();
+    loop {
+      let start = tokens.len();
+      if !self.value_tokens(tokens)? {
+        break;
+      }
+      starts.push(start);
+      if !self.eat(TokenKind::Comma)? {
+        break;
+      }
+    }
+    Ok(starts.len() - count)
+  }
+
+  fn value_tokens(&mut self, tokens: &mut Vec<Token<'a>>) -> Result<bool, SyntaxError> {
+    let count = tokens.len();
+    match self.current.kind {
+      TokenKind::GlobalName(_) | TokenKind::LocalName(_) => {
+        while let TokenKind::GlobalName(_) | TokenKind::LocalName(_) = self.current.kind {
+          tokens.push(self.advance()?);
+        }
+      },
+      // Adjacent strings are concatenated into one value
+      TokenKind::Str(_) => {
+        while let TokenKind::Str(_) = self.current.kind {
+          tokens.push(self.advance()?);
+        }
+      },
+      TokenKind::Identifier(_) | TokenKind::Number(_) | TokenKind::Char(_) => {
+        tokens.push(self.advance()?);
+      },
+      _ => {},
+    }
+    Ok(tokens.len() > count)
+  }
+
+  fn property(&mut self) -> Result<Property, SyntaxError> {
+    let token = self.advance()?;
+    let key = match token.kind {
+      TokenKind::Identifier(key) => key.to_string(),
+      kind => return Err(error_at(token, format!("Expected a property identifier but found {:?}", kind))),
+    };
+
+    if !self.eat(TokenKind::Equals)? {
+      return Ok(Property { key, value: Literal::Bool(true) });
+    }
+
+    let mut value = Vec::new();
+    if !self.value_tokens(&mut value)? {
+      let token = self.current;
+      return Err(error_at(token, format!("Expected a value for property {}", key)));
+    }
+
+    Ok(Property { key, value: self.literal(&value)? })
+  }
+
+  fn literal(&self, value: &[Token<'a>]) -> Result<Literal, SyntaxError> {
+    let token = value[0];
+    let literal = match token.kind {
+      TokenKind::GlobalName(_) | TokenKind::LocalName(_) => Literal::Ref(self.reference(value)?),
+      TokenKind::Str(_) => Literal::String(self.string(value)?),
+      TokenKind::Char(_) => Literal::Integer(integer_value(DataType::Int64, token)? as i64),
+      TokenKind::Identifier("true") => Literal::Bool(true),
+      TokenKind::Identifier("false") => Literal::Bool(false),
+      TokenKind::Identifier("null") => Literal::Ref(Reference { names: Vec::new() }),
+      TokenKind::Identifier(identifier) => match DataType::from_identifier(identifier) {
+        Some(data_type) => Literal::Type(data_type),
+        None => return Err(error_at(token, format!("Unexpected identifier {}", identifier))),
+      },
+      TokenKind::Number(number) => {
+        let int = integer_literal(number).and_then(|(negative, magnitude)| {
+          let magnitude = i64::try_from(magnitude).ok()?;
+          Some(if negative { -magnitude } else { magnitude })
+        });
+        if let Some(int) = int {
+          Literal::Integer(int)
+        } else if let Some(float) = float_literal(number, 64) {
+          Literal::Float(float)
+        } else {
+          return Err(error_at(token, format!("Invalid number {}", number)));
+        }
+      },
+      kind => return Err(error_at(token, format!("Unexpected {:?}", kind))),
+    };
+    Ok(literal)
+  }
+
+  fn reference(&self, value: &[Token<'a>]) -> Result<Reference, SyntaxError> {
+    let mut names = Vec::with_capacity(value.len());
+    for (i, token) in value.iter().enumerate() {
+      match token.kind {
+        TokenKind::GlobalName(name) if i == 0 => names.push(Name::Global(name.to_string())),
+        TokenKind::LocalName(name) => names.push(Name::Local(name.to_string())),
+        TokenKind::Identifier("null") if value.len() == 1 => {},
+        kind => return Err(error_at(*token, format!("Invalid reference {:?}", kind))),
+      }
+    }
+    Ok(Reference { names })
+  }
+
+  // Concatenates the string literals that make up a value
+  fn string(&self, value: &[Token<'a>]) -> Result<String, SyntaxError> {
+    let mut string = String::new();
+    for token in value {
+      match token.kind {
+        TokenKind::Str(raw) => match unescape(raw) {
+          Some(unescaped) => string.push_str(&unescaped),
+          None => return Err(error_at(*token, "Invalid escape sequence in string".to_string())),
+        },
+        _ => return Err(error_at(*token, "Expected a string value".to_string())),
+      }
+    }
+    Ok(string)
+  }
+
+}
+
+// Something read while streaming through a file. Every StructureBegin is
+// matched by a StructureEnd, with the structure's properties, then its data
+// or substructures in between.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event<'a> {
+  StructureBegin {
+    identifier: &'a str,
+    name: Option<Name>,
+    line: usize,
+    column: usize,
+  },
+  Property(Property),
+  Data(DataList),
+  StructureEnd,
+}
+
+// Streams through a file one event at a time without building the structure
+// tree, so only the data of the current primitive structure is held in
+// memory. Stops after the first syntax error.
+//
+// The source is borrowed rather than read, since events borrow identifiers
+// from it. Files too large to read into a String are meant to be memory
+// mapped, with the memmap2 crate for example, and passed in through
+// std::str::from_utf8, which checks the mapping without copying it. Only
+// the pages being parsed are then read from disk.
+pub struct Events<'a> {
+  source: &'a str,
+  parser: Option<Parser<'a>>,
+  // The identifiers of the derived structures that haven't ended yet
+  open: Vec<&'a str>,
+  pending: VecDeque<Event<'a>>,
+  done: bool,
+}
+
+impl<'a> Events<'a> {
+  pub fn new(source: &'a str) -> Events<'a> {
+    Events {
+      source,
+      parser: None,
+      open: Vec::new(),
+      pending: VecDeque::new(),
+      done: false,
+    }
+  }
+
+  // How many structures the next event is nested inside
+  pub fn depth(&self) -> usize {
+    self.open.len()
+  }
+
+  fn read(&mut self) -> Result<Option<Event<'a>>, SyntaxError> {
+    if self.parser.is_none() {
+      self.parser = Some(Parser::new(self.source)?);
+    }
+    match self.parser {
+      Some(ref mut parser) => parser.event(&mut self.open, &mut self.pending),
+      None => Ok(None),
+    }
+  }
+}
+
+impl<'a> Iterator for Events<'a> {
+  type Item = Result<Event<'a>, SyntaxError>;
+
+  fn next(&mut self) -> Option<Result<Event<'a>, SyntaxError>> {
+    if let Some(event) = self.pending.pop_front() {
+      return Some(Ok(event));
+    }
+    if self.done {
+      return None;
+    }
+    match self.read() {
+      Ok(Some(event)) => Some(Ok(event)),
+      Ok(None) => {
+        self.done = true;
+        None
+      },
+      Err(error) => {
+        self.done = true;
+        Some(Err(error))
+      },
+    }
+  }
+}
+
+pub fn parse(source: &str) -> Result<Vec<Structure>, SyntaxError> {
+  let mut structures = Vec::new();
+  let mut open: Vec<Structure> = Vec::new();
+  for event in Events::new(source) {
+    match event? {
+      Event::StructureBegin { identifier, name, line, column } => {
+        open.push(Structure {
+          identifier: identifier.to_string(),
+          name,
+          properties: Vec::new(),
+          data: None,
+          children: Vec::new(),
+          line,
+          column,
+        });
+      },
+      Event::Property(property) => {
+        if let Some(structure) = open.last_mut() {
+          structure.properties.push(property);
+        }
+      },
+      Event::Data(data) => {
+        if let Some(structure) = open.last_mut() {
+          structure.data = Some(data);
+        }
+      },
+      Event::StructureEnd => {
+        if let Some(structure) = open.pop() {
+          match open.last_mut() {
+            Some(parent) => parent.children.push(structure),
+            None => structures.push(structure),
+          }
+        }
+      },
+    }
+  }
+  Ok(structures)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResolveError {
+  // A global name used twice in the file, or a local name used twice by
+  // the substructures of one structure
+  Duplicate {
+    name: String,
+    line: usize,
+    column: usize,
+  },
+  Unresolved {
+    reference: String,
+    line: usize,
+    column: usize,
+  },
+}
+
+impl fmt::Display for ResolveError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ResolveError::Duplicate { ref name, line, column } => {
+        write!(f, "Duplicate name {} at line {}, column {}", name, line, column)
+      },
+      ResolveError::Unresolved { ref reference, line, column } => {
+        write!(f, "Unresolved reference {} at line {}, column {}", reference, line, column)
+      },
+    }
+  }
+}
+
+// Finds the structures that references refer to. Global names can be used
+// from anywhere, local names are looked up in the structure containing the
+// reference, then each structure around it and finally the top level.
+pub struct Resolver<'a> {
+  document: &'a Document,
+  globals: HashMap<&'a str, &'a Structure>,
+  parents: HashMap<*const Structure, &'a Structure>,
+}
+
+impl<'a> Resolver<'a> {
+  pub fn new(document: &'a Document) -> Result<Resolver<'a>, ResolveError> {
+    let mut resolver = Resolver {
+      document,
+      globals: HashMap::new(),
+      parents: HashMap::new(),
+    };
+
+    check_local_names(&document.structures)?;
+    for structure in document.iter() {
+      if let Some(Name::Global(ref name)) = structure.name {
+        if resolver.globals.insert(name, structure).is_some() {
+          return Err(ResolveError::Duplicate {
+            name: format!("${}", name),
+            line: structure.line,
+            column: structure.column,
+          });
+        }
+      }
+      check_local_names(&structure.children)?;
+      for child in &structure.children {
+        resolver.parents.insert(child as *const Structure, structure);
+      }
+    }
+
+    Ok(resolver)
+  }
+
+  pub fn parent(&self, structure: &Structure) -> Option<&'a Structure> {
+    self.parents.get(&(structure as *const Structure)).cloned()
+  }
+
+  // The names leading to a structure from the nearest global name or the
+  // top level, like $node1%bone2. None when a structure on the way has no
+  // name.
+  pub fn path(&self, structure: &'a Structure) -> Option<String> {
+    let mut names = Vec::new();
+    let mut current = structure;
+    loop {
+      let name = current.name.as_ref()?;
+      names.push(name.to_string());
+      if let Name::Global(_) = *name {
+        break;
+      }
+      match self.parent(current) {
+        Some(parent) => current = parent,
+        None => break,
+      }
+    }
+    names.reverse();
+    Some(names.concat())
+  }
+
+  // The structure a reference made from within from refers to, None for a
+  // null reference
+  pub fn resolve(&self, reference: &Reference, from: &'a Structure) -> Result<Option<&'a Structure>, ResolveError> {
+    let unresolved = || ResolveError::Unresolved {
+      reference: reference.to_string(),
+      line: from.line,
+      column: from.column,
+    };
+
+    let (first, rest) = match reference.names.split_first() {
+      Some(names) => names,
+      None => return Ok(None),
+    };
+    let mut structure = match *first {
+      Name::Global(ref name) => self.globals.get(name.as_str()).cloned(),
+      Name::Local(ref name) => {
+        let mut scope = Some(from);
+        let mut found = None;
+        while let Some(structure) = scope {
+          found = local_child(&structure.children, name);
+          if found.is_some() {
+            break;
+          }
+          scope = self.parent(structure);
+        }
+        found.or_else(|| local_child(&self.document.structures, name))
+      },
+    };
+    for name in rest {
+      structure = match (name, structure) {
+        (Name::Local(name), Some(parent)) => local_child(&parent.children, name),
+        _ => None,
+      };
+    }
+
+    structure.map(Some).ok_or_else(unresolved)
+  }
+
+  // Resolves every reference in the document, in properties and ref data
+  pub fn check(&self) -> Result<(), ResolveError> {
+    for structure in self.document.iter() {
+      for property in &structure.properties {
+        if let Literal::Ref(ref reference) = property.value {
+          self.resolve(reference, structure)?;
+        }
+      }
+      if let Some(references) = structure.data.as_ref().and_then(|data| data.references()) {
+        // Report the structure the data belongs to rather than the ref
+        // structure itself
+        let owner = self.parent(structure).unwrap_or(structure);
+        for reference in references {
+          self.resolve(reference, structure).map_err(|error| match error {
+            ResolveError::Unresolved { reference, .. } => ResolveError::Unresolved { reference, line: owner.line, column: owner.column },
+            error => error,
+          })?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn local_child<'a>(structures: &'a [Structure], name: &str) -> Option<&'a Structure> {
+  structures.iter().find(|structure| match structure.name {
+    Some(Name::Local(ref local)) => local == name,
+    _ => false,
+  })
+}
+
+fn check_local_names(structures: &[Structure]) -> Result<(), ResolveError> {
+  for (i, structure) in structures.iter().enumerate() {
+    if let Some(Name::Local(ref name)) = structure.name {
+      if local_child(&structures[..i], name).is_some() {
+        return Err(ResolveError::Duplicate {
+          name: format!("%{}", name),
+          line: structure.line,
+          column: structure.column,
+        });
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn header_brace_and_data_on_one_line() {
+    let structures = parse("GeometryNode $node1 { Name {string {\"Cube\"}} Transform { float[16] {{1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1}} } }").unwrap();
+    assert_eq!(structures.len(), 1);
+    let node = &structures[0];
+    assert_eq!(node.identifier, "GeometryNode");
+    assert_eq!(node.name, Some(Name::Global("node1".to_string())));
+    let name = node.child("Name").and_then(|name| name.primitive()).unwrap();
+    assert_eq!(name.strings().unwrap(), &["Cube".to_string()]);
+    let transform = node.child("Transform").and_then(|transform| transform.primitive()).unwrap();
+    assert_eq!(transform.array_size, 16);
+    assert_eq!(transform.as_f32s().unwrap().len(), 16);
+  }
+
+  #[test]
+  fn properties_and_references() {
+    let structures = parse("Track (target = %yrot) { Key (kind = \"-control\", index = 2) {ref {$a%b, null}} }").unwrap();
+    let track = &structures[0];
+    assert_eq!(track.property("target"), Some(&Literal::Ref(Reference { names: vec![Name::Local("yrot".to_string())] })));
+    let key = track.child("Key").unwrap();
+    assert_eq!(key.property("kind").and_then(|kind| kind.as_str()), Some("-control"));
+    assert_eq!(key.property("index").and_then(|index| index.as_i64()), Some(2));
+    let references = key.primitive().and_then(|data| data.references()).unwrap();
+    assert_eq!(references[0].names, vec![Name::Global("a".to_string()), Name::Local("b".to_string())]);
+    assert!(references[1].names.is_empty());
+  }
+
+  #[test]
+  fn reports_location_of_syntax_errors() {
+    let error = parse("Metric (key = \"distance\")\n{float {1.0}").unwrap_err();
+    assert_eq!(error.line, 2);
+    
+    // Lines inside block comments still count
+    let error = parse("/* one\ntwo */ Metric {float {1.0}} ]").unwrap_err();
+    assert_eq!((error.line, error.column), (2, 29));
+    let error = parse("Metric {float {1.0}}\n  /* never closed").unwrap_err();
+    assert_eq!((error.line, error.column), (2, 3));
+  }
+
+  #[test]
+  fn every_primitive_type_and_literal() {
+    let source = "
+      bool {true, false}
+      int8 {-128, 0x7f, 'A'}
+      int32 {0b1010_1010, 0o17, '\\x01ab'}
+      unsigned_int16 {65535, 1_000}
+      unsigned_int64 {0xffffffffffffffff}
+      half {0x3c00, -0x4000, 0.5}
+      float {0x3f800000, 1.5e3, .25, 2.}
+      double {0x3ff0000000000000, -1e-300}
+      string {\"tab\\there\" \" and \\u00e9\", \"\\x41\\\"\"}
+      type {float, u8}
+    ";
+    let structures = parse(source).unwrap();
+    let data: Vec<&Data> = structures.iter().map(|structure| &structure.data.as_ref().unwrap().data).collect();
+    assert_eq!(*data[0], Data::Bool(vec![true, false]));
+    assert_eq!(*data[1], Data::Int8(vec![-128, 127, 65]));
+    assert_eq!(*data[2], Data::Int32(vec![170, 15, 0x0001_6162]));
+    assert_eq!(*data[3], Data::UnsignedInt16(vec![65535, 1000]));
+    assert_eq!(*data[4], Data::UnsignedInt64(vec![u64::MAX]));
+    assert_eq!(*data[5], Data::Half(vec![1.0, -2.0, 0.5]));
+    assert_eq!(*data[6], Data::Float(vec![1.0, 1500.0, 0.25, 2.0]));
+    assert_eq!(*data[7], Data::Double(vec![1.0, -1e-300]));
+    assert_eq!(*data[8], Data::String(vec!["tab\there and \u{e9}".to_string(), "A\"".to_string()]));
+    assert_eq!(*data[9], Data::Type(vec![DataType::Float, DataType::UnsignedInt8]));
+    
+    assert!(parse("int8 {128}").is_err());
+    assert!(parse("unsigned_int8 {-1}").is_err());
+    assert!(parse("half {0x10000}").is_err());
+    assert!(parse("string {\"\\q\"}").is_err());
+    assert!(parse("float {1__0}").is_err());
+    
+    let structures = parse("Key (mask = 0xff, letter = 'z') {}").unwrap();
+    assert_eq!(structures[0].property("mask").and_then(|mask| mask.as_i64()), Some(255));
+    assert_eq!(structures[0].property("letter").and_then(|letter| letter.as_i64()), Some(122));
+  }
+
+  #[test]
+  fn document_traversal_and_lookup() {
+    let document: Document = "
+      GeometryNode $node1 {
+        TriggerVolume %trigger (shape = \"box\") {float[3] {{1, 2, 3}}}
+        GameplayTag {string {\"door\"}}
+        GameplayTag {string {\"locked\"}}
+      }
+      GameplayTag $tag {string {\"level\"}}
+    ".parse().unwrap();
+    
+    let identifiers: Vec<&str> = document.iter().map(|structure| structure.identifier.as_str()).collect();
+    assert_eq!(identifiers, vec!["GeometryNode", "TriggerVolume", "float", "GameplayTag", "string", "GameplayTag", "string", "GameplayTag", "string"]);
+    assert_eq!(document.structures_with("GameplayTag").count(), 3);
+    
+    let node = document.find("$node1").unwrap();
+    let tags: Vec<&str> = node.children_with("GameplayTag")
+      .filter_map(|tag| tag.primitive().and_then(|data| data.strings()))
+      .map(|strings| strings[0].as_str())
+      .collect();
+    assert_eq!(tags, vec!["door", "locked"]);
+    
+    let trigger = node.local("%trigger").unwrap();
+    assert_eq!(trigger.property("shape").and_then(|shape| shape.as_str()), Some("box"));
+    let data = trigger.primitive().unwrap();
+    assert!(!trigger.is_primitive() && trigger.primitive_structure().unwrap().is_primitive());
+    assert_eq!((data.len(), data.floats()), (1, Some(&[1.0, 2.0, 3.0][..])));
+    assert_eq!(data.doubles(), None);
+    
+    assert_eq!(document.find("tag").map(|tag| tag.name.as_ref().unwrap().to_string()), Some("$tag".to_string()));
+    let reference = Reference { names: vec![Name::Global("a".to_string()), Name::Local("b".to_string())] };
+    assert_eq!(reference.to_string(), "$a%b");
+    assert_eq!(Reference { names: Vec::new() }.to_string(), "null");
+  }
+
+  #[test]
+  fn resolves_reference_paths() {
+    let document: Document = "
+      Node $a {
+        Group %b {
+          Item %c {int32 {1}}
+        }
+        Track (target = %b) {}
+        Link {ref {$a%b%c, null, %b%c}}
+      }
+      Other %b {}
+    ".parse().unwrap();
+    let resolver = Resolver::new(&document).unwrap();
+    resolver.check().unwrap();
+    
+    let a = document.find("$a").unwrap();
+    let link = a.child("Link").unwrap().primitive_structure().unwrap();
+    let references = link.data.as_ref().unwrap().references().unwrap();
+    let c = a.local("b").unwrap().local("c").unwrap();
+    assert!(std::ptr::eq(resolver.resolve(&references[0], link).unwrap().unwrap(), c));
+    assert_eq!(resolver.resolve(&references[1], link).unwrap(), None);
+    // Local names are looked up in the enclosing structures before the top level
+    assert!(std::ptr::eq(resolver.resolve(&references[2], link).unwrap().unwrap(), c));
+    let track = a.child("Track").unwrap();
+    let target = track.property("target").unwrap().as_reference().unwrap();
+    assert!(std::ptr::eq(resolver.resolve(target, track).unwrap().unwrap(), a.local("b").unwrap()));
+    
+    let document: Document = "Node $a {Link {ref {$a%missing}}}".parse().unwrap();
+    match Resolver::new(&document).unwrap().check() {
+      Err(ResolveError::Unresolved { reference, line, column }) => assert_eq!((reference.as_str(), line, column), ("$a%missing", 1, 10)),
+      result => panic!("expected an unresolved reference, found {:?}", result),
+    }
+    
+    let document: Document = "Node $a {} Node {Node $a {}}".parse().unwrap();
+    assert!(matches!(Resolver::new(&document), Err(ResolveError::Duplicate { .. })));
+    let document: Document = "Node {Group %b {} Group %b {}} Node {Group %b {}}".parse().unwrap();
+    match Resolver::new(&document) {
+      Err(ResolveError::Duplicate { name, column, .. }) => assert_eq!((name.as_str(), column), ("%b", 19)),
+      _ => panic!("expected a duplicate name"),
+    }
+  }
+
+  #[test]
+  fn streams_events() {
+    let events: Vec<Event> = Events::new("Mesh $m (primitive = \"triangles\") {IndexArray {unsigned_int16[3] {{0, 1, 2}}}}")
+      .collect::<Result<_, _>>()
+      .unwrap();
+    assert_eq!(events, vec![
+      Event::StructureBegin { identifier: "Mesh", name: Some(Name::Global("m".to_string())), line: 1, column: 1 },
+      Event::Property(Property { key: "primitive".to_string(), value: Literal::String("triangles".to_string()) }),
+      Event::StructureBegin { identifier: "IndexArray", name: None, line: 1, column: 36 },
+      Event::StructureBegin { identifier: "unsigned_int16", name: None, line: 1, column: 48 },
+      Event::Data(DataList { array_size: 3, data: Data::UnsignedInt16(vec![0, 1, 2]) }),
+      Event::StructureEnd,
+      Event::StructureEnd,
+      Event::StructureEnd,
+    ]);
+    
+    // Count the triangles of a file without building the structure tree
+    let source = ::std::fs::read_to_string("examples/data/Holostand/Holostand.ogex").unwrap();
+    let mut events = Events::new(&source);
+    let mut in_index_array = None;
+    let mut triangles = 0;
+    while let Some(event) = events.next() {
+      match event.unwrap() {
+        Event::StructureBegin { identifier: "IndexArray", .. } => in_index_array = Some(events.depth()),
+        Event::Data(ref data) if in_index_array.is_some() => triangles += data.len(),
+        Event::StructureEnd if in_index_array == Some(events.depth() + 1) => in_index_array = None,
+        _ => {},
+      }
+    }
+    let document: Document = source.parse().unwrap();
+    let expected: usize = document.structures_with("IndexArray")
+      .filter_map(|structure| structure.primitive())
+      .map(|data| data.len())
+      .sum();
+    assert!(triangles > 0);
+    assert_eq!(triangles, expected);
+    
+    let mut events = Events::new("Mesh {IndexArray {");
+    assert_eq!(events.next().map(|event| event.is_ok()), Some(true));
+    assert_eq!(events.next().map(|event| event.is_ok()), Some(true));
+    assert_eq!(events.next().unwrap().unwrap_err().message, "Unexpected end of file inside IndexArray");
+    assert!(events.next().is_none());
+  }
+
+  #[test]
+  fn decodes_long_numeric_arrays() {
+    let count = 50_000;
+    let values: Vec<String> = (0..count).map(|i| format!("{{{}.5, -{}, 1_0.25}}", i, i)).collect();
+    let source = format!("float[3] {{{}}}\nunsigned_int32 {{{}}}", values.join(","), (0..count).map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+    let structures = parse(&source).unwrap();
+    let floats = structures[0].data.as_ref().and_then(|data| data.floats()).unwrap();
+    assert_eq!(floats.len(), count*3);
+    assert_eq!(&floats[3*40_000..3*40_001], &[40_000.5, -40_000.0, 10.25]);
+    let ints = structures[1].data.as_ref().and_then(|data| data.unsigned_int32s()).unwrap();
+    assert!(ints.iter().enumerate().all(|(i, &int)| int as usize == i));
+    
+    // Decimal floats are rounded once, straight to f32
+    let structures = parse("float {0.1, 16777217, 3.4028235e38}").unwrap();
+    assert_eq!(structures[0].data.as_ref().and_then(|data| data.floats()), Some(&[0.1f32, 16_777_216.0, f32::MAX][..]));
+    
+    // The first invalid value is reported, wherever it is in the array
+    let mut values = vec!["1".to_string(); count];
+    values[45_000] = "256".to_string();
+    values[30_000] = "-1".to_string();
+    let error = parse(&format!("unsigned_int8 {{{}}}", values.join(","))).unwrap_err();
+    assert_eq!(error.message, "-1 is out of range for UnsignedInt8");
+    
+    // Lines are counted through comments and character literals, and
+    // braces or commas inside them don't split the list
+    let mut lines = vec!["1,".to_string(); count];
+    lines[10] = "/* }, { */ 2, // },".to_string();
+    lines[20_000] = "'}',".to_string();
+    lines[40_000] = "  1.5,".to_string();
+    let error = parse(&format!("unsigned_int16 {{\n{}\n}}", lines.join("\n"))).unwrap_err();
+    assert_eq!((error.message.as_str(), error.line, error.column), ("Invalid UnsignedInt16 value 1.5", 40_002, 3));
+    lines[40_000] = "3,".to_string();
+    let structures = parse(&format!("unsigned_int16 {{\n{}\n}} Next {{}}", lines.join("\n"))).unwrap();
+    let ints = structures[0].data.as_ref().and_then(|data| data.unsigned_int16s()).unwrap();
+    assert_eq!((ints.len(), ints[10], ints[20_000], ints[40_000]), (count, 2, 0x7d, 3));
+    assert_eq!((structures[1].identifier.as_str(), structures[1].line), ("Next", count + 2));
+  }
+}
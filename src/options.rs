@@ -0,0 +1,152 @@
+use cgmath::Vector3;
+
+use extension::ExtensionRegistry;
+use scalar::Scalar;
+
+// A direction along one of the coordinate axes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+  X,
+  NegativeX,
+  Y,
+  NegativeY,
+  Z,
+  NegativeZ,
+}
+
+impl Axis {
+  // Parses a direction as Metric writes it, like "z" or "-x"
+  pub fn from_name(name: &str) -> Option<Axis> {
+    match name {
+      "x" | "+x" => Some(Axis::X),
+      "-x" => Some(Axis::NegativeX),
+      "y" | "+y" => Some(Axis::Y),
+      "-y" => Some(Axis::NegativeY),
+      "z" | "+z" => Some(Axis::Z),
+      "-z" => Some(Axis::NegativeZ),
+      _ => None,
+    }
+  }
+
+  pub fn vector(self) -> Vector3<Scalar> {
+    match self {
+      Axis::X => Vector3::new(1.0, 0.0, 0.0),
+      Axis::NegativeX => Vector3::new(-1.0, 0.0, 0.0),
+      Axis::Y => Vector3::new(0.0, 1.0, 0.0),
+      Axis::NegativeY => Vector3::new(0.0, -1.0, 0.0),
+      Axis::Z => Vector3::new(0.0, 0.0, 1.0),
+      Axis::NegativeZ => Vector3::new(0.0, 0.0, -1.0),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Handedness {
+  Right,
+  Left,
+}
+
+// The coordinate system the model is converted to. OpenGEX files are right
+// handed with the up and forward directions given by their Metric, or z up
+// and +y forward when it doesn't give them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConversionOptions {
+  pub up: Axis,
+  // The direction a model faces
+  pub forward: Axis,
+  pub handedness: Handedness,
+}
+
+impl ConversionOptions {
+  // Y up, -Z forward and right handed, like OpenGL
+  pub fn new() -> ConversionOptions {
+    ConversionOptions {
+      up: Axis::Y,
+      forward: Axis::NegativeZ,
+      handedness: Handedness::Right,
+    }
+  }
+
+  // Y up, +Z forward and left handed, like Direct3D
+  pub fn left_handed() -> ConversionOptions {
+    ConversionOptions {
+      up: Axis::Y,
+      forward: Axis::Z,
+      handedness: Handedness::Left,
+    }
+  }
+}
+
+impl Default for ConversionOptions {
+  fn default() -> ConversionOptions {
+    ConversionOptions::new()
+  }
+}
+
+// The units a model is converted to, as meters and seconds per unit. Angles
+// are always converted to radians, which transforms are built from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Units {
+  pub distance: Scalar,
+  pub time: Scalar,
+}
+
+impl Units {
+  // Meters and seconds
+  pub fn new() -> Units {
+    Units {
+      distance: 1.0,
+      time: 1.0,
+    }
+  }
+}
+
+impl Default for Units {
+  fn default() -> Units {
+    Units::new()
+  }
+}
+
+// How a file is loaded
+#[derive(Clone)]
+pub struct ParseOptions {
+  // Whether structures, properties, attribs and values the crate doesn't
+  // support, and extensions without a handler, are errors. Otherwise
+  // they're skipped and listed in the model's warnings.
+  pub strict: bool,
+  pub extensions: ExtensionRegistry,
+  // None keeps the axes the file was written in
+  pub conversion: Option<ConversionOptions>,
+  // None keeps the distance and time units the file was written in. Angles
+  // are converted to radians either way.
+  pub units: Option<Units>,
+  // Whether the models get_vertex and get_normal return have their node's
+  // transforms applied. Otherwise they're left in object space, and the
+  // instances in the same order say where each one goes.
+  pub bake: bool,
+}
+
+impl ParseOptions {
+  pub fn new() -> ParseOptions {
+    ParseOptions {
+      strict: false,
+      extensions: ExtensionRegistry::new(),
+      conversion: None,
+      units: None,
+      bake: true,
+    }
+  }
+
+  pub fn strict() -> ParseOptions {
+    ParseOptions {
+      strict: true,
+      ..ParseOptions::new()
+    }
+  }
+}
+
+impl Default for ParseOptions {
+  fn default() -> ParseOptions {
+    ParseOptions::new()
+  }
+}
@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+
+use cgmath::Matrix4;
+use cgmath::Vector3;
+use cgmath::Vector4;
+use cgmath::Quaternion;
+
+use openddl::{DataList, Literal};
+
+// The type geometry, transforms and animation are loaded as. The f64
+// feature keeps the precision of double arrays and large translations.
+//
+// The feature changes the types of the public API rather than adding to it,
+// so enabling it anywhere in a build changes them for every crate using
+// this one. Only applications should enable it, never libraries, and code
+// meant to build either way should use Scalar and ToF32 instead of f32.
+#[cfg(not(feature = "f64"))]
+pub type Scalar = f32;
+#[cfg(feature = "f64")]
+pub type Scalar = f64;
+
+// Any floating point data as Scalars, only copying if it had to be converted
+#[cfg(not(feature = "f64"))]
+pub(crate) fn scalars(data: &DataList) -> Option<Cow<'_, [Scalar]>> {
+  data.as_f32s()
+}
+
+#[cfg(feature = "f64")]
+pub(crate) fn scalars(data: &DataList) -> Option<Cow<'_, [Scalar]>> {
+  data.as_f64s()
+}
+
+#[cfg(not(feature = "f64"))]
+pub(crate) fn scalar(literal: &Literal) -> Option<Scalar> {
+  literal.as_f32()
+}
+
+#[cfg(feature = "f64")]
+pub(crate) fn scalar(literal: &Literal) -> Option<Scalar> {
+  literal.as_f64()
+}
+
+// Converts loaded data down to f32, like when handing it to a renderer
+pub trait ToF32 {
+  type Output;
+  
+  fn to_f32(&self) -> Self::Output;
+}
+
+impl ToF32 for Scalar {
+  type Output = f32;
+  
+  #[allow(clippy::unnecessary_cast)]
+  fn to_f32(&self) -> f32 {
+    *self as f32
+  }
+}
+
+impl<const N: usize> ToF32 for [Scalar; N] {
+  type Output = [f32; N];
+  
+  fn to_f32(&self) -> [f32; N] {
+    let mut converted = [0.0; N];
+    for (converted, value) in converted.iter_mut().zip(self.iter()) {
+      *converted = value.to_f32();
+    }
+    converted
+  }
+}
+
+impl ToF32 for Vector3<Scalar> {
+  type Output = Vector3<f32>;
+  
+  fn to_f32(&self) -> Vector3<f32> {
+    Vector3::new(self.x.to_f32(), self.y.to_f32(), self.z.to_f32())
+  }
+}
+
+impl ToF32 for Vector4<Scalar> {
+  type Output = Vector4<f32>;
+  
+  fn to_f32(&self) -> Vector4<f32> {
+    Vector4::new(self.x.to_f32(), self.y.to_f32(), self.z.to_f32(), self.w.to_f32())
+  }
+}
+
+impl ToF32 for Quaternion<Scalar> {
+  type Output = Quaternion<f32>;
+  
+  fn to_f32(&self) -> Quaternion<f32> {
+    Quaternion::from_sv(self.s.to_f32(), self.v.to_f32())
+  }
+}
+
+impl ToF32 for Matrix4<Scalar> {
+  type Output = Matrix4<f32>;
+  
+  fn to_f32(&self) -> Matrix4<f32> {
+    Matrix4::from_cols(self.x.to_f32(), self.y.to_f32(), self.z.to_f32(), self.w.to_f32())
+  }
+}
+
+impl<T: ToF32> ToF32 for [T] {
+  type Output = Vec<T::Output>;
+  
+  fn to_f32(&self) -> Vec<T::Output> {
+    self.iter().map(|value| value.to_f32()).collect()
+  }
+}
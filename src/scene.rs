@@ -0,0 +1,182 @@
+use cgmath::Matrix4;
+use cgmath::SquareMatrix;
+
+use animation::Animation;
+use extension::Extension;
+use scalar::Scalar;
+use transform::NodeTransform;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialRef {
+  pub index: u32,
+  pub material_ref: String,
+}
+
+// The weight of one of the object's morph targets
+#[derive(Clone, Debug, PartialEq)]
+pub struct MorphWeight {
+  // The local name an animation track targets, like %mw1. Empty if the
+  // structure isn't named.
+  pub name: String,
+  pub index: u32,
+  pub weight: Scalar,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeometryNode {
+  pub object_ref: String,
+  pub material_refs: Vec<MaterialRef>,
+  pub morph_weights: Vec<MorphWeight>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightNode {
+  pub object_ref: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraNode {
+  pub object_ref: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoneNode {
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeKind {
+  Node,
+  Geometry(GeometryNode),
+  Light(LightNode),
+  Camera(CameraNode),
+  Bone(BoneNode),
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+  // The structure name, like $node1
+  pub node_ref: String,
+  // The contents of the node's Name structure
+  pub name: String,
+  pub kind: NodeKind,
+  // The node's transform structures in file order
+  pub transforms: Vec<NodeTransform>,
+  // The product of the transforms that are inherited by subnodes
+  pub local_transform: Matrix4<Scalar>,
+  pub world_transform: Matrix4<Scalar>,
+  // The product of the object only transforms, applied after world_transform
+  pub object_transform: Matrix4<Scalar>,
+  pub children: Vec<Node>,
+  // One animation per clip
+  pub animations: Vec<Animation>,
+  pub extensions: Vec<Extension>,
+}
+
+impl Node {
+  pub fn new(kind: NodeKind) -> Node {
+    Node {
+      node_ref: "".to_string(),
+      name: "".to_string(),
+      kind,
+      transforms: Vec::new(),
+      local_transform: Matrix4::identity(),
+      world_transform: Matrix4::identity(),
+      object_transform: Matrix4::identity(),
+      children: Vec::new(),
+      animations: Vec::new(),
+      extensions: Vec::new(),
+    }
+  }
+
+  pub fn geometry(&self) -> Option<&GeometryNode> {
+    match self.kind {
+      NodeKind::Geometry(ref geometry) => Some(geometry),
+      _ => None,
+    }
+  }
+
+  // Recomputes local_transform and object_transform from the transform
+  // stack, multiplying each transform in the order it appears in the file
+  pub fn update_local_transform(&mut self) {
+    self.local_transform = Matrix4::identity();
+    self.object_transform = Matrix4::identity();
+    for transform in &self.transforms {
+      if transform.object {
+        self.object_transform = self.object_transform*transform.matrix();
+      } else {
+        self.local_transform = self.local_transform*transform.matrix();
+      }
+    }
+  }
+
+  // Recomputes the world transforms of this node and its subnodes
+  pub fn update_world_transform(&mut self, parent: &Matrix4<Scalar>) {
+    self.world_transform = parent*self.local_transform;
+    let world_transform = self.world_transform;
+    for child in &mut self.children {
+      child.update_world_transform(&world_transform);
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Scene {
+  // The top level nodes of the file
+  pub nodes: Vec<Node>,
+}
+
+impl Scene {
+  pub fn new() -> Scene {
+    Scene {
+      nodes: Vec::new(),
+    }
+  }
+
+  // Every node in the scene, parents before their children
+  pub fn iter(&self) -> NodeIter<'_> {
+    NodeIter {
+      stack: self.nodes.iter().rev().collect(),
+    }
+  }
+
+  // Finds a node by its name, like $node1, or by the path of local names
+  // leading to it from a global name or the top level, like $node1%bone2
+  pub fn find(&self, node_ref: &str) -> Option<&Node> {
+    let mut starts: Vec<usize> = node_ref.match_indices(['$', '%']).map(|(i, _)| i).collect();
+    if starts.first() != Some(&0) {
+      return self.iter().find(|node| node.node_ref == node_ref);
+    }
+    starts.push(node_ref.len());
+    let names: Vec<&str> = starts.windows(2).map(|range| &node_ref[range[0]..range[1]]).collect();
+
+    let mut node = if names[0].starts_with('$') {
+      self.iter().find(|node| node.node_ref == names[0])?
+    } else {
+      self.nodes.iter().find(|node| node.node_ref == names[0])?
+    };
+    for name in &names[1..] {
+      node = node.children.iter().find(|child| child.node_ref == *name)?;
+    }
+    Some(node)
+  }
+}
+
+impl Default for Scene {
+  fn default() -> Scene {
+    Scene::new()
+  }
+}
+
+pub struct NodeIter<'a> {
+  stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for NodeIter<'a> {
+  type Item = &'a Node;
+
+  fn next(&mut self) -> Option<&'a Node> {
+    let node = self.stack.pop()?;
+    self.stack.extend(node.children.iter().rev());
+    Some(node)
+  }
+}
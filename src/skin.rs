@@ -0,0 +1,59 @@
+use cgmath::Matrix4;
+use cgmath::SquareMatrix;
+
+use scalar::Scalar;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skeleton {
+  // The BoneNodes the mesh is bound to, like $node3
+  pub bone_refs: Vec<String>,
+  // The world transform of each bone when the mesh was bound to it
+  pub bind_transforms: Vec<Matrix4<Scalar>>,
+}
+
+impl Skeleton {
+  pub fn new() -> Skeleton {
+    Skeleton {
+      bone_refs: Vec::new(),
+      bind_transforms: Vec::new(),
+    }
+  }
+}
+
+impl Default for Skeleton {
+  fn default() -> Skeleton {
+    Skeleton::new()
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoneWeight {
+  // An index into the skeleton's bones
+  pub bone: u32,
+  pub weight: Scalar,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skin {
+  // The transform of the mesh when it was bound to the skeleton
+  pub bind_transform: Matrix4<Scalar>,
+  pub skeleton: Skeleton,
+  // The bones influencing each vertex of the mesh
+  pub weights: Vec<Vec<BoneWeight>>,
+}
+
+impl Skin {
+  pub fn new() -> Skin {
+    Skin {
+      bind_transform: Matrix4::identity(),
+      skeleton: Skeleton::new(),
+      weights: Vec::new(),
+    }
+  }
+}
+
+impl Default for Skin {
+  fn default() -> Skin {
+    Skin::new()
+  }
+}
@@ -0,0 +1,132 @@
+use cgmath::Rad;
+use cgmath::Matrix4;
+use cgmath::Vector3;
+use cgmath::Quaternion;
+use cgmath::InnerSpace;
+use cgmath::SquareMatrix;
+
+use animation::TrackValue;
+use scalar::Scalar;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Translation {
+  X(Scalar),
+  Y(Scalar),
+  Z(Scalar),
+  Xyz(Vector3<Scalar>),
+}
+
+// Angles are in radians, whatever units the file was written in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+  X(Scalar),
+  Y(Scalar),
+  Z(Scalar),
+  Axis(Scalar, Vector3<Scalar>),
+  Quaternion(Quaternion<Scalar>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scale {
+  X(Scalar),
+  Y(Scalar),
+  Z(Scalar),
+  Xyz(Vector3<Scalar>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransformValue {
+  Transform(Matrix4<Scalar>),
+  Translation(Translation),
+  Rotation(Rotation),
+  Scale(Scale),
+}
+
+impl TransformValue {
+  // Replaces the value with an animated one, ignoring values of the wrong shape
+  pub fn set(&mut self, value: TrackValue) {
+    match (self, value) {
+      (&mut TransformValue::Transform(ref mut matrix), TrackValue::Matrix(value)) => *matrix = value,
+      (&mut TransformValue::Translation(Translation::X(ref mut x)), TrackValue::Float(value)) |
+      (&mut TransformValue::Translation(Translation::Y(ref mut x)), TrackValue::Float(value)) |
+      (&mut TransformValue::Translation(Translation::Z(ref mut x)), TrackValue::Float(value)) |
+      (&mut TransformValue::Rotation(Rotation::X(ref mut x)), TrackValue::Float(value)) |
+      (&mut TransformValue::Rotation(Rotation::Y(ref mut x)), TrackValue::Float(value)) |
+      (&mut TransformValue::Rotation(Rotation::Z(ref mut x)), TrackValue::Float(value)) |
+      (&mut TransformValue::Scale(Scale::X(ref mut x)), TrackValue::Float(value)) |
+      (&mut TransformValue::Scale(Scale::Y(ref mut x)), TrackValue::Float(value)) |
+      (&mut TransformValue::Scale(Scale::Z(ref mut x)), TrackValue::Float(value)) => *x = value,
+      (&mut TransformValue::Translation(Translation::Xyz(ref mut vector)), TrackValue::Vector(value)) |
+      (&mut TransformValue::Scale(Scale::Xyz(ref mut vector)), TrackValue::Vector(value)) => *vector = value,
+      (&mut TransformValue::Rotation(Rotation::Axis(ref mut angle, ref mut axis)), TrackValue::Axis(value, value_axis)) => {
+        *angle = value;
+        *axis = value_axis;
+      },
+      (&mut TransformValue::Rotation(Rotation::Quaternion(ref mut quaternion)), TrackValue::Quaternion(value)) => *quaternion = value,
+      _ => {},
+    }
+  }
+
+  pub fn matrix(&self) -> Matrix4<Scalar> {
+    match *self {
+      TransformValue::Transform(matrix) => matrix,
+      TransformValue::Translation(translation) => {
+        let offset = match translation {
+          Translation::X(x) => Vector3::new(x, 0.0, 0.0),
+          Translation::Y(y) => Vector3::new(0.0, y, 0.0),
+          Translation::Z(z) => Vector3::new(0.0, 0.0, z),
+          Translation::Xyz(offset) => offset,
+        };
+        Matrix4::from_translation(offset)
+      },
+      TransformValue::Rotation(rotation) => {
+        match rotation {
+          Rotation::X(angle) => Matrix4::from_angle_x(Rad(angle)),
+          Rotation::Y(angle) => Matrix4::from_angle_y(Rad(angle)),
+          Rotation::Z(angle) => Matrix4::from_angle_z(Rad(angle)),
+          Rotation::Axis(angle, axis) => {
+            if axis.magnitude2() == 0.0 {
+              Matrix4::identity()
+            } else {
+              Matrix4::from_axis_angle(axis.normalize(), Rad(angle))
+            }
+          },
+          Rotation::Quaternion(quaternion) => Matrix4::from(quaternion),
+        }
+      },
+      TransformValue::Scale(scale) => {
+        match scale {
+          Scale::X(x) => Matrix4::from_nonuniform_scale(x, 1.0, 1.0),
+          Scale::Y(y) => Matrix4::from_nonuniform_scale(1.0, y, 1.0),
+          Scale::Z(z) => Matrix4::from_nonuniform_scale(1.0, 1.0, z),
+          Scale::Xyz(scale) => Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z),
+        }
+      },
+    }
+  }
+}
+
+// One of a node's Transform, Translation, Rotation or Scale structures
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeTransform {
+  // The local name used to target the transform from an animation track,
+  // like %yrot. Empty if the structure isn't named.
+  pub name: String,
+  // Object only transforms apply to the node's object but not its subnodes
+  pub object: bool,
+  pub value: TransformValue,
+}
+
+impl NodeTransform {
+  pub fn new(value: TransformValue) -> NodeTransform {
+    NodeTransform {
+      name: "".to_string(),
+      object: false,
+      value,
+    }
+  }
+
+  pub fn matrix(&self) -> Matrix4<Scalar> {
+    self.value.matrix()
+  }
+}
@@ -0,0 +1,749 @@
+use std::fmt;
+
+use error::Location;
+use openddl::{DataList, DataType, Document, Literal, Reference, Resolver, ResolveError, Structure};
+
+// A way a document breaks the OpenGEX 3.0 specification
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+  pub location: Location,
+  pub message: String,
+}
+
+impl fmt::Display for Violation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}: {}", self.location, self.message)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+  Bool,
+  Unsigned,
+  Float,
+  String,
+  Ref,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Count {
+  Optional,
+  One,
+  AtLeastOne,
+  Any,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Primitive {
+  Float,
+  UnsignedInt,
+  String,
+  Ref,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Data {
+  kind: Primitive,
+  // The allowed array sizes, 0 for a flat list
+  sizes: &'static [usize],
+  // Whether the data is a single value rather than a list
+  single: bool,
+}
+
+const FLOAT: Data = Data { kind: Primitive::Float, sizes: &[0], single: true };
+const FLOATS: Data = Data { kind: Primitive::Float, sizes: &[0], single: false };
+const STRING: Data = Data { kind: Primitive::String, sizes: &[0], single: true };
+const REF: Data = Data { kind: Primitive::Ref, sizes: &[0], single: true };
+const REFS: Data = Data { kind: Primitive::Ref, sizes: &[0], single: false };
+const UNSIGNED_INTS: Data = Data { kind: Primitive::UnsignedInt, sizes: &[0], single: false };
+
+// What a structure can contain. Data that depends on a property, like the
+// size of a VertexArray, is checked separately.
+struct Rule {
+  identifier: &'static str,
+  properties: &'static [(&'static str, Value)],
+  required: &'static [&'static str],
+  children: &'static [(&'static str, Count)],
+  data: Option<Data>,
+}
+
+const RULES: &[Rule] = &[
+  Rule {
+    identifier: "Animation",
+    properties: &[("clip", Value::Unsigned), ("begin", Value::Float), ("end", Value::Float)],
+    required: &[],
+    children: &[("Track", Count::AtLeastOne)],
+    data: None,
+  },
+  Rule {
+    identifier: "Atten",
+    properties: &[("kind", Value::String), ("curve", Value::String)],
+    required: &[],
+    children: &[("Param", Count::Any)],
+    data: None,
+  },
+  Rule {
+    identifier: "BoneCountArray",
+    properties: &[],
+    required: &[],
+    children: &[],
+    data: Some(UNSIGNED_INTS),
+  },
+  Rule {
+    identifier: "BoneIndexArray",
+    properties: &[],
+    required: &[],
+    children: &[],
+    data: Some(UNSIGNED_INTS),
+  },
+  Rule {
+    identifier: "BoneNode",
+    properties: &[],
+    required: &[],
+    children: &[
+      ("Name", Count::Optional), ("Transform", Count::Any), ("Translation", Count::Any), ("Rotation", Count::Any),
+      ("Scale", Count::Any), ("Animation", Count::Any), ("Node", Count::Any), ("BoneNode", Count::Any),
+      ("GeometryNode", Count::Any), ("CameraNode", Count::Any), ("LightNode", Count::Any), ("Extension", Count::Any),
+    ],
+    data: None,
+  },
+  Rule {
+    identifier: "BoneRefArray",
+    properties: &[],
+    required: &[],
+    children: &[],
+    data: Some(REFS),
+  },
+  Rule {
+    identifier: "BoneWeightArray",
+    properties: &[],
+    required: &[],
+    children: &[],
+    data: Some(FLOATS),
+  },
+  Rule {
+    identifier: "CameraNode",
+    properties: &[],
+    required: &[],
+    children: &[
+      ("Name", Count::Optional), ("ObjectRef", Count::One), ("Transform", Count::Any), ("Translation", Count::Any),
+      ("Rotation", Count::Any), ("Scale", Count::Any), ("Animation", Count::Any), ("Node", Count::Any),
+      ("BoneNode", Count::Any), ("GeometryNode", Count::Any), ("CameraNode", Count::Any), ("LightNode", Count::Any),
+      ("Extension", Count::Any),
+    ],
+    data: None,
+  },
+  Rule {
+    identifier: "CameraObject",
+    properties: &[],
+    required: &[],
+    children: &[("Param", Count::Any), ("Color", Count::Any), ("Texture", Count::Any), ("Extension", Count::Any)],
+    data: None,
+  },
+  Rule {
+    identifier: "Clip",
+    properties: &[("index", Value::Unsigned)],
+    required: &[],
+    children: &[("Name", Count::Optional), ("Param", Count::Any), ("Extension", Count::Any)],
+    data: None,
+  },
+  Rule {
+    identifier: "Color",
+    properties: &[("attrib", Value::String)],
+    required: &["attrib"],
+    children: &[],
+    data: Some(Data { kind: Primitive::Float, sizes: &[3, 4], single: true }),
+  },
+  Rule {
+    identifier: "Extension",
+    properties: &[("applic", Value::String), ("type", Value::String)],
+    required: &[],
+    children: &[],
+    data: None,
+  },
+  Rule {
+    identifier: "GeometryNode",
+    properties: &[("visible", Value::Bool), ("shadow", Value::Bool), ("motion_blur", Value::Bool)],
+    required: &[],
+    children: &[
+      ("Name", Count::Optional), ("ObjectRef", Count::One), ("MaterialRef", Count::Any), ("MorphWeight", Count::Any),
+      ("Transform", Count::Any), ("Translation", Count::Any), ("Rotation", Count::Any), ("Scale", Count::Any),
+      ("Animation", Count::Any), ("Node", Count::Any), ("BoneNode", Count::Any), ("GeometryNode", Count::Any),
+      ("CameraNode", Count::Any), ("LightNode", Count::Any), ("Extension", Count::Any),
+    ],
+    data: None,
+  },
+  Rule {
+    identifier: "GeometryObject",
+    properties: &[("visible", Value::Bool), ("shadow", Value::Bool), ("motion_blur", Value::Bool)],
+    required: &[],
+    children: &[("Mesh", Count::AtLeastOne), ("Morph", Count::Any), ("Extension", Count::Any)],
+    data: None,
+  },
+  Rule {
+    identifier: "IndexArray",
+    properties: &[("material", Value::Unsigned), ("restart", Value::Unsigned), ("front", Value::String)],
+    required: &[],
+    children: &[],
+    data: None,
+  },
+  Rule {
+    identifier: "Key",
+    properties: &[("kind", Value::String)],
+    required: &[],
+    children: &[],
+    data: None,
+  },
+  Rule {
+    identifier: "LightNode",
+    properties: &[("shadow", Value::Bool)],
+    required: &[],
+    children: &[
+      ("Name", Count::Optional), ("ObjectRef", Count::One), ("Transform", Count::Any), ("Translation", Count::Any),
+      ("Rotation", Count::Any), ("Scale", Count::Any), ("Animation", Count::Any), ("Node", Count::Any),
+      ("BoneNode", Count::Any), ("GeometryNode", Count::Any), ("CameraNode", Count::Any), ("LightNode", Count::Any),
+      ("Extension", Count::Any),
+    ],
+    data: None,
+  },
+  Rule {
+    identifier: "LightObject",
+    properties: &[("type", Value::String), ("shadow", Value::Bool)],
+    required: &["type"],
+    children: &[
+      ("Color", Count::Any), ("Spectrum", Count::Any), ("Param", Count::Any), ("Texture", Count::Any),
+      ("Atten", Count::Any), ("Extension", Count::Any),
+    ],
+    data: None,
+  },
+  Rule {
+    identifier: "Material",
+    properties: &[("two_sided", Value::Bool)],
+    required: &[],
+    children: &[
+      ("Name", Count::Optional), ("Color", Count::Any), ("Spectrum", Count::Any), ("Param", Count::Any),
+      ("Texture", Count::Any), ("Extension", Count::Any),
+    ],
+    data: None,
+  },
+  Rule {
+    identifier: "MaterialRef",
+    properties: &[("index", Value::Unsigned)],
+    required: &[],
+    children: &[],
+    data: Some(REF),
+  },
+  Rule {
+    identifier: "Mesh",
+    properties: &[("lod", Value::Unsigned), ("primitive", Value::String)],
+    required: &[],
+    children: &[("VertexArray", Count::AtLeastOne), ("IndexArray", Count::Any), ("Skin", Count::Optional), ("Extension", Count::Any)],
+    data: None,
+  },
+  Rule {
+    identifier: "Metric",
+    properties: &[("key", Value::String)],
+    required: &["key"],
+    children: &[],
+    data: None,
+  },
+  Rule {
+    identifier: "Morph",
+    properties: &[("index", Value::Unsigned), ("base", Value::Unsigned)],
+    required: &[],
+    children: &[("Name", Count::Optional), ("Extension", Count::Any)],
+    data: None,
+  },
+  Rule {
+    identifier: "MorphWeight",
+    properties: &[("index", Value::Unsigned)],
+    required: &[],
+    children: &[],
+    data: Some(FLOAT),
+  },
+  Rule {
+    identifier: "Name",
+    properties: &[],
+    required: &[],
+    children: &[],
+    data: Some(STRING),
+  },
+  Rule {
+    identifier: "Node",
+    properties: &[],
+    required: &[],
+    children: &[
+      ("Name", Count::Optional), ("Transform", Count::Any), ("Translation", Count::Any), ("Rotation", Count::Any),
+      ("Scale", Count::Any), ("Animation", Count::Any), ("Node", Count::Any), ("BoneNode", Count::Any),
+      ("GeometryNode", Count::Any), ("CameraNode", Count::Any), ("LightNode", Count::Any), ("Extension", Count::Any),
+    ],
+    data: None,
+  },
+  Rule {
+    identifier: "ObjectRef",
+    properties: &[],
+    required: &[],
+    children: &[],
+    data: Some(REF),
+  },
+  Rule {
+    identifier: "Param",
+    properties: &[("attrib", Value::String)],
+    required: &["attrib"],
+    children: &[],
+    data: Some(FLOAT),
+  },
+  Rule {
+    identifier: "Rotation",
+    properties: &[("kind", Value::String), ("object", Value::Bool)],
+    required: &[],
+    children: &[],
+    data: None,
+  },
+  Rule {
+    identifier: "Scale",
+    properties: &[("kind", Value::String), ("object", Value::Bool)],
+    required: &[],
+    children: &[],
+    data: None,
+  },
+  Rule {
+    identifier: "Skeleton",
+    properties: &[],
+    required: &[],
+    children: &[("BoneRefArray", Count::One), ("Transform", Count::One)],
+    data: None,
+  },
+  Rule {
+    identifier: "Skin",
+    properties: &[],
+    required: &[],
+    children: &[
+      ("Transform", Count::Optional), ("Skeleton", Count::One), ("BoneCountArray", Count::One),
+      ("BoneIndexArray", Count::One), ("BoneWeightArray", Count::One),
+    ],
+    data: None,
+  },
+  Rule {
+    identifier: "Spectrum",
+    properties: &[("attrib", Value::String), ("min", Value::Unsigned), ("max", Value::Unsigned)],
+    required: &["attrib"],
+    children: &[],
+    data: Some(FLOATS),
+  },
+  Rule {
+    identifier: "Texture",
+    properties: &[
+      ("attrib", Value::String), ("texcoord", Value::Unsigned), ("swizzle", Value::String), ("x_address", Value::String),
+      ("y_address", Value::String), ("z_address", Value::String), ("border", Value::String),
+    ],
+    required: &["attrib"],
+    children: &[
+      ("Transform", Count::Any), ("Translation", Count::Any), ("Rotation", Count::Any), ("Scale", Count::Any),
+      ("Animation", Count::Any),
+    ],
+    data: Some(STRING),
+  },
+  Rule {
+    identifier: "Time",
+    properties: &[("curve", Value::String)],
+    required: &[],
+    children: &[("Key", Count::AtLeastOne)],
+    data: None,
+  },
+  Rule {
+    identifier: "Track",
+    properties: &[("target", Value::Ref)],
+    required: &["target"],
+    children: &[("Time", Count::One), ("Value", Count::One)],
+    data: None,
+  },
+  Rule {
+    identifier: "Transform",
+    properties: &[("object", Value::Bool)],
+    required: &[],
+    children: &[],
+    data: Some(Data { kind: Primitive::Float, sizes: &[16], single: false }),
+  },
+  Rule {
+    identifier: "Translation",
+    properties: &[("kind", Value::String), ("object", Value::Bool)],
+    required: &[],
+    children: &[],
+    data: None,
+  },
+  Rule {
+    identifier: "Value",
+    properties: &[("curve", Value::String)],
+    required: &[],
+    children: &[("Key", Count::AtLeastOne)],
+    data: None,
+  },
+  Rule {
+    identifier: "VertexArray",
+    properties: &[("attrib", Value::String), ("morph", Value::Unsigned)],
+    required: &["attrib"],
+    children: &[],
+    data: None,
+  },
+];
+
+const TOP_LEVEL: &[&str] = &[
+  "Metric", "Node", "BoneNode", "GeometryNode", "CameraNode", "LightNode", "GeometryObject", "CameraObject",
+  "LightObject", "Material", "Clip", "Extension",
+];
+
+fn rule(identifier: &str) -> Option<&'static Rule> {
+  RULES.iter().find(|rule| rule.identifier == identifier)
+}
+
+// Whether OpenGEX 3.0 defines a structure with the identifier
+pub(crate) fn is_opengex_structure(identifier: &str) -> bool {
+  rule(identifier).is_some()
+}
+
+pub(crate) fn is_opengex_property(identifier: &str, key: &str) -> bool {
+  rule(identifier).is_some_and(|rule| rule.properties.iter().any(|&(name, _)| name == key))
+}
+
+fn location(structure: &Structure) -> Location {
+  Location::new(structure.line, structure.column)
+}
+
+fn describe(kind: Primitive, size: usize) -> String {
+  let kind = match kind {
+    Primitive::Float => "float",
+    Primitive::UnsignedInt => "unsigned_int",
+    Primitive::String => "string",
+    Primitive::Ref => "ref",
+  };
+  if size == 0 { kind.to_string() } else { format!("{}[{}]", kind, size) }
+}
+
+fn is_kind(data: &DataList, kind: Primitive) -> bool {
+  matches!((data.data_type(), kind),
+    (DataType::Half, Primitive::Float) | (DataType::Float, Primitive::Float) | (DataType::Double, Primitive::Float) |
+    (DataType::UnsignedInt8, Primitive::UnsignedInt) | (DataType::UnsignedInt16, Primitive::UnsignedInt) |
+    (DataType::UnsignedInt32, Primitive::UnsignedInt) | (DataType::UnsignedInt64, Primitive::UnsignedInt) |
+    (DataType::String, Primitive::String) | (DataType::Ref, Primitive::Ref))
+}
+
+fn is_value(literal: &Literal, value: Value) -> bool {
+  match (literal, value) {
+    (&Literal::Bool(_), Value::Bool) | (&Literal::String(_), Value::String) | (&Literal::Ref(_), Value::Ref) => true,
+    (&Literal::Integer(int), Value::Unsigned) => int >= 0,
+    (&Literal::Integer(_), Value::Float) | (&Literal::Float(_), Value::Float) => true,
+    _ => false,
+  }
+}
+
+struct Validator<'a> {
+  resolver: Option<Resolver<'a>>,
+  violations: Vec<Violation>,
+}
+
+impl<'a> Validator<'a> {
+  fn report(&mut self, structure: &Structure, message: String) {
+    self.violations.push(Violation { location: location(structure), message });
+  }
+
+  fn structure(&mut self, structure: &'a Structure) {
+    let rule = match rule(&structure.identifier) {
+      Some(rule) => rule,
+      None => return,
+    };
+
+    for property in &structure.properties {
+      match rule.properties.iter().find(|&&(key, _)| key == property.key) {
+        Some(&(key, value)) if !is_value(&property.value, value) => {
+          self.report(structure, format!("The {} property of {} should be a {:?}", key, structure.identifier, value));
+        },
+        Some(_) => {},
+        None => self.report(structure, format!("Unknown property {} of {}", property.key, structure.identifier)),
+      }
+    }
+    for &key in rule.required {
+      if structure.property(key).is_none() {
+        self.report(structure, format!("{} is missing its {} property", structure.identifier, key));
+      }
+    }
+
+    // Extensions can contain anything
+    if rule.identifier == "Extension" {
+      return;
+    }
+    self.children(structure, rule);
+    if let Some(data) = self.expected_data(structure, rule) {
+      self.data(structure, data);
+    }
+
+    match rule.identifier {
+      "GeometryNode" => self.object_ref(structure, "GeometryObject"),
+      "LightNode" => self.object_ref(structure, "LightObject"),
+      "CameraNode" => self.object_ref(structure, "CameraObject"),
+      "MaterialRef" => self.references(structure, "Material"),
+      "BoneRefArray" => self.references(structure, "BoneNode"),
+      "Mesh" => self.mesh(structure),
+      "Track" => self.track(structure),
+      _ => {},
+    }
+
+    for child in structure.children.iter().filter(|child| !child.is_primitive()) {
+      self.structure(child);
+    }
+  }
+
+  fn children(&mut self, structure: &Structure, rule: &Rule) {
+    for child in structure.children.iter().filter(|child| !child.is_primitive()) {
+      if !rule.children.iter().any(|&(identifier, _)| identifier == child.identifier) {
+        let message = if is_opengex_structure(&child.identifier) {
+          format!("{} is not allowed in {}", child.identifier, structure.identifier)
+        } else {
+          format!("Unknown structure {} in {}", child.identifier, structure.identifier)
+        };
+        self.report(child, message);
+      }
+    }
+    for &(identifier, count) in rule.children {
+      let found = structure.children_with(identifier).count();
+      let expected = match count {
+        Count::One if found != 1 => "exactly one",
+        Count::Optional if found > 1 => "at most one",
+        Count::AtLeastOne if found == 0 => "at least one",
+        _ => continue,
+      };
+      self.report(structure, format!("{} should contain {} {} but has {}", structure.identifier, expected, identifier, found));
+    }
+  }
+
+  // The data the structure should hold, which for some structures depends on
+  // its properties
+  fn expected_data(&mut self, structure: &Structure, rule: &Rule) -> Option<Data> {
+    let property = |key| structure.property(key).and_then(|value| value.as_str());
+    let sizes: &'static [usize] = match rule.identifier {
+      "Translation" | "Scale" => match property("kind") {
+        Some("x") | Some("y") | Some("z") => return Some(FLOAT),
+        None | Some("xyz") => &[3],
+        Some(kind) => {
+          self.report(structure, format!("Unknown kind {} for {}", kind, structure.identifier));
+          return None;
+        },
+      },
+      "Rotation" => match property("kind") {
+        Some("x") | Some("y") | Some("z") => return Some(FLOAT),
+        None | Some("axis") | Some("quaternion") => &[4],
+        Some(kind) => {
+          self.report(structure, format!("Unknown kind {} for {}", kind, structure.identifier));
+          return None;
+        },
+      },
+      "VertexArray" => {
+        // Attribs can have an index, like texcoord[1]
+        let attrib = property("attrib").map(|attrib| attrib.split('[').next().unwrap_or(attrib));
+        let sizes: &'static [usize] = match attrib {
+          Some("position") | Some("normal") | Some("tangent") | Some("bitangent") => &[3],
+          Some("color") => &[3, 4],
+          Some("texcoord") => &[2, 3],
+          _ => &[],
+        };
+        return Some(Data { kind: Primitive::Float, sizes, single: false });
+      },
+      "Key" => return Some(Data { kind: Primitive::Float, sizes: &[], single: false }),
+      "IndexArray" => {
+        let mesh = self.resolver.as_ref().and_then(|resolver| resolver.parent(structure));
+        let sizes: &'static [usize] = match mesh.and_then(|mesh| mesh.property("primitive")).and_then(|primitive| primitive.as_str()) {
+          Some("points") | Some("line_strip") | Some("triangle_strip") => &[0],
+          Some("lines") => &[2],
+          None | Some("triangles") => &[3],
+          Some("quads") => &[4],
+          Some(_) => &[],
+        };
+        return Some(Data { kind: Primitive::UnsignedInt, sizes, single: false });
+      },
+      "Metric" => {
+        return match property("key") {
+          Some("distance") | Some("angle") | Some("time") => Some(FLOAT),
+          Some(key @ "up") | Some(key @ "forward") => {
+            self.metric_axis(structure, key);
+            Some(STRING)
+          },
+          Some(key) => {
+            self.report(structure, format!("Unknown Metric key {}", key));
+            None
+          },
+          None => None,
+        };
+      },
+      _ => return rule.data,
+    };
+    // The transforms left hold a single vector, quaternion or matrix
+    Some(Data { kind: Primitive::Float, sizes, single: true })
+  }
+
+  fn data(&mut self, structure: &Structure, expected: Data) {
+    let primitives: Vec<&Structure> = structure.children.iter().filter(|child| child.is_primitive()).collect();
+    let data = match primitives.first().and_then(|primitive| primitive.data.as_ref()) {
+      Some(data) if primitives.len() == 1 => data,
+      _ => {
+        self.report(structure, format!("{} should contain one data structure but has {}", structure.identifier, primitives.len()));
+        return;
+      },
+    };
+
+    // An empty size list allows any array size
+    let size_ok = expected.sizes.is_empty() || expected.sizes.contains(&data.array_size);
+    if !is_kind(data, expected.kind) || !size_ok {
+      let size = if expected.sizes.len() == 1 { expected.sizes[0] } else { data.array_size };
+      let mut expected_type = describe(expected.kind, size);
+      if expected.sizes.len() > 1 {
+        let sizes: Vec<String> = expected.sizes.iter().map(|&size| describe(expected.kind, size)).collect();
+        expected_type = sizes.join(" or ");
+      }
+      self.report(structure, format!("Expected {} data in {} but found {}", expected_type, structure.identifier, data.type_name()));
+    } else if expected.single && data.len() != 1 {
+      self.report(structure, format!("{} should hold one value but has {}", structure.identifier, data.len()));
+    }
+  }
+
+  fn metric_axis(&mut self, structure: &Structure, key: &str) {
+    let axis = structure.primitive().and_then(|data| data.strings()).and_then(|strings| strings.first());
+    let valid = match (key, axis.map(|axis| axis.as_str())) {
+      ("up", Some("y")) | ("up", Some("z")) => true,
+      ("forward", Some(axis)) => matches!(axis, "x" | "y" | "z" | "-x" | "-y" | "-z"),
+      _ => false,
+    };
+    if let (false, Some(axis)) = (valid, axis) {
+      self.report(structure, format!("{} is not a valid {} direction", axis, key));
+    }
+  }
+
+  // Resolves a reference, reporting it if it doesn't resolve
+  fn resolve(&mut self, reference: &Reference, from: &'a Structure, owner: &Structure) -> Option<&'a Structure> {
+    let result = match self.resolver {
+      Some(ref resolver) => resolver.resolve(reference, from),
+      None => return None,
+    };
+    match result {
+      Ok(structure) => structure,
+      Err(_) => {
+        self.report(owner, format!("Unresolved reference {}", reference));
+        None
+      },
+    }
+  }
+
+  fn object_ref(&mut self, node: &'a Structure, identifier: &str) {
+    if let Some(object_ref) = node.child("ObjectRef") {
+      self.references(object_ref, identifier);
+    }
+  }
+
+  // Checks the references in a structure's data refer to structures with
+  // the identifier
+  fn references(&mut self, structure: &'a Structure, identifier: &str) {
+    let primitive = match structure.primitive_structure() {
+      Some(primitive) => primitive,
+      None => return,
+    };
+    for reference in primitive.data.as_ref().and_then(|data| data.references()).unwrap_or(&[]) {
+      if let Some(target) = self.resolve(reference, primitive, structure) {
+        if target.identifier != identifier {
+          self.report(structure, format!("{} refers to a {} rather than a {}", structure.identifier, target.identifier, identifier));
+        }
+      }
+    }
+  }
+
+  fn mesh(&mut self, mesh: &Structure) {
+    let arrays: Vec<(&Structure, usize)> = mesh.children_with("VertexArray")
+      .filter_map(|array| array.primitive().map(|data| (array, data.len())))
+      .collect();
+    let vertex_count = match arrays.iter().find(|&&(array, _)| array.property("attrib").and_then(|attrib| attrib.as_str()) == Some("position")).or_else(|| arrays.first()) {
+      Some(&(_, count)) => count,
+      None => return,
+    };
+    for &(array, count) in &arrays {
+      if count != vertex_count {
+        self.report(array, format!("VertexArray has {} vertices but the Mesh has {}", count, vertex_count));
+      }
+    }
+
+    for index_array in mesh.children_with("IndexArray") {
+      let restart = index_array.property("restart").and_then(|restart| restart.as_i64());
+      let indices = index_array.primitive().and_then(|data| data.unsigned_integers()).unwrap_or_default();
+      let out_of_range = indices.iter().find(|&&index| index as usize >= vertex_count && Some(i64::from(index)) != restart);
+      if let Some(index) = out_of_range {
+        self.report(index_array, format!("Index {} is out of range for {} vertices", index, vertex_count));
+      }
+    }
+  }
+
+  fn track(&mut self, track: &'a Structure) {
+    let keys: Vec<(&Structure, usize)> = ["Time", "Value"].iter()
+      .filter_map(|&identifier| track.child(identifier))
+      .flat_map(|child| child.children_with("Key"))
+      .filter_map(|key| key.primitive().map(|data| (key, data.len())))
+      .collect();
+    if let Some(&(_, count)) = keys.first() {
+      for &(key, found) in &keys {
+        if found != count {
+          self.report(key, format!("Key has {} values but the Track has {} keys", found, count));
+        }
+      }
+    }
+
+    // Tracks animate the transforms and morph weights of the structure the
+    // Animation is in
+    let target = match track.property("target").and_then(|target| target.as_reference()) {
+      Some(target) => target,
+      None => return,
+    };
+    let node = self.resolver.as_ref()
+      .and_then(|resolver| resolver.parent(track))
+      .and_then(|animation| self.resolver.as_ref().and_then(|resolver| resolver.parent(animation)));
+    if let Some(resolved) = self.resolve(target, track, track) {
+      let animatable = matches!(resolved.identifier.as_str(), "Transform" | "Translation" | "Rotation" | "Scale" | "MorphWeight");
+      let in_node = node.is_some_and(|node| node.children.iter().any(|child| ::std::ptr::eq(child, resolved)));
+      if !animatable || !in_node {
+        self.report(track, format!("Track target {} is not a transform or morph weight beside the Animation", target));
+      }
+    }
+  }
+}
+
+// Checks a document against the OpenGEX 3.0 specification, returning every
+// violation found in file order
+pub fn validate(document: &Document) -> Vec<Violation> {
+  let mut validator = Validator {
+    resolver: None,
+    violations: Vec::new(),
+  };
+
+  match Resolver::new(document) {
+    Ok(resolver) => validator.resolver = Some(resolver),
+    // References can't be checked without unique names
+    Err(ResolveError::Duplicate { name, line, column }) => validator.violations.push(Violation {
+      location: Location::new(line, column),
+      message: format!("Duplicate name {}", name),
+    }),
+    Err(ResolveError::Unresolved { reference, line, column }) => validator.violations.push(Violation {
+      location: Location::new(line, column),
+      message: format!("Unresolved reference {}", reference),
+    }),
+  }
+
+  for structure in &document.structures {
+    if !TOP_LEVEL.contains(&structure.identifier.as_str()) {
+      let message = if is_opengex_structure(&structure.identifier) {
+        format!("{} is not allowed at the top level", structure.identifier)
+      } else {
+        format!("Unknown structure {}", structure.identifier)
+      };
+      validator.report(structure, message);
+    }
+    validator.structure(structure);
+  }
+
+  validator.violations.sort_by_key(|violation| (violation.location.line, violation.location.column));
+  validator.violations
+}